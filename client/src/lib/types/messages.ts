@@ -1,32 +1,74 @@
+/*
+* Payloads are generated from the server types, see protocol.ts
+* */
+import type * as protocol from './protocol';
+import type {
+    ConfirmConnectPayload,
+    ErrorPayload,
+    RoomConfig,
+    MatchmakingSuccessPayload,
+    MakeActionPayload,
+    RoundFinishedPayload,
+    GameFinishedPayload,
+    RoomSnapshotPayload,
+    RoundStartedPayload,
+    PrivateRoomCreatedPayload,
+    JoinPrivateRoomPayload,
+    RematchPayload,
+    ProfilePayload,
+    LeaderboardPayload,
+    ServerShuttingDownPayload,
+} from './protocol';
+
+export * from './protocol';
+
+
 /*
 * Base type 
 * */
 type Message<T, D> = {
     type: T,
     data: D,
+    // Chosen by the client and echoed on the direct reply, absent on pushes
+    request_id?: string,
 }
 
 
 /*
 * Confirm connect
 * */
-export type ConfirmConnectPayload = {
-    message: string,
-}
+// Version of the protocol this client speaks, sent when connecting
+export const PROTOCOL_VERSION = 2;
 
 export const confirmConnectType = 'ConfirmConnect';
 export type ConfirmConnectMessage = Message<typeof confirmConnectType, ConfirmConnectPayload>
 
 
+/*
+* Error
+* */
+export const errorType = 'Error';
+export type ErrorMessage = Message<typeof errorType, ErrorPayload>
+
+
 /*
 * Start matchmaking 
 * */
-export type StartMatchmakingPayload = null
+export type StartMatchmakingPayload = RoomConfig | null
 
 export const startMatchmakingType = 'StartMatchmaking'
 export type StartMatchmakingMessage = Message<typeof startMatchmakingType, StartMatchmakingPayload>
 
 
+/*
+* Cancel matchmaking
+* */
+export type CancelMatchmakingPayload = null
+
+export const cancelMatchmakingType = 'CancelMatchmaking'
+export type CancelMatchmakingMessage = Message<typeof cancelMatchmakingType, CancelMatchmakingPayload>
+
+
 /*
 * Matchmaking started
 * */
@@ -37,13 +79,17 @@ export type MatchmakingStartedMessage = Message<typeof matchmakingStartedType, M
 
 
 /*
-* Matchmaking success
+* Matchmaking cancelled
 * */
-export type MatchmakingSuccessPayload = {
-    room: string,
-    opponent: string,
-}
+export type MatchmakingCancelledPayload = null
+
+export const matchmakingCancelledType = 'MatchmakingCancelled'
+export type MatchmakingCancelledMessage = Message<typeof matchmakingCancelledType, MatchmakingCancelledPayload>
 
+
+/*
+* Matchmaking success
+* */
 export const matchmakingSuccessType = 'MatchmakingSuccess'
 export type MatchmakingSuccessMessage = Message<typeof matchmakingSuccessType, MatchmakingSuccessPayload>
 
@@ -51,17 +97,6 @@ export type MatchmakingSuccessMessage = Message<typeof matchmakingSuccessType, M
 /*
 * Make action
 * */
-export enum Action {
-    Rock = 'Rock',
-    Paper = 'Paper',
-    Scissors = 'Scissors',
-}
-
-export type MakeActionPayload = {
-    room: string,
-    action: Action,
-}
-
 export const makeActionType = 'MakeAction'
 export type MakeActionMessage = Message<typeof makeActionType, MakeActionPayload>
 
@@ -72,23 +107,12 @@ export type MakeActionMessage = Message<typeof makeActionType, MakeActionPayload
 export type MakeActionSuccessPayload = null;
 
 export const makeActionSuccessType = 'MakeActionSuccess'
-export type MakeActionSuccessMessage = Message<typeof makeActionSuccessType, MakeActionPayload>
+export type MakeActionSuccessMessage = Message<typeof makeActionSuccessType, MakeActionSuccessPayload>
 
 
 /*
 * Round finished
 * */
-export type ActionHistory = {
-    user_id: number,
-    action: Action,
-}
-
-export type RoundFinishedPayload = {
-    winner: number,
-    actions: ActionHistory[],
-    next_round_count: number,
-};
-
 export const roundFinishedType = 'RoundFinished'
 export type RoundFinishedMessage = Message<typeof roundFinishedType, RoundFinishedPayload>
 
@@ -96,10 +120,94 @@ export type RoundFinishedMessage = Message<typeof roundFinishedType, RoundFinish
 /*
 * Game finished
 * */
-export type GameFinishedPayload = {
-    winner: number,
-    actions: ActionHistory[],
-};
-
 export const gameFinishedType = 'GameFinished'
 export type GameFinishedMessage = Message<typeof gameFinishedType, GameFinishedPayload>
+
+
+/*
+* Resume session
+* */
+export const resumeSessionType = 'ResumeSession'
+export type ResumeSessionMessage = Message<typeof resumeSessionType, RoomSnapshotPayload>
+
+
+/*
+* Round started
+* */
+export const roundStartedType = 'RoundStarted'
+export type RoundStartedMessage = Message<typeof roundStartedType, RoundStartedPayload>
+
+
+/*
+* Create private room
+* */
+export type CreatePrivateRoomPayload = RoomConfig | null
+
+export const createPrivateRoomType = 'CreatePrivateRoom'
+export type CreatePrivateRoomMessage = Message<typeof createPrivateRoomType, CreatePrivateRoomPayload>
+
+
+/*
+* Private room created
+* */
+export const privateRoomCreatedType = 'PrivateRoomCreated'
+export type PrivateRoomCreatedMessage = Message<typeof privateRoomCreatedType, PrivateRoomCreatedPayload>
+
+
+/*
+* Join private room
+* */
+export const joinPrivateRoomType = 'JoinPrivateRoom'
+export type JoinPrivateRoomMessage = Message<typeof joinPrivateRoomType, JoinPrivateRoomPayload>
+
+
+/*
+* Rematch
+* */
+export const requestRematchType = 'RequestRematch'
+export type RequestRematchMessage = Message<typeof requestRematchType, RematchPayload>
+
+export const declineRematchType = 'DeclineRematch'
+export type DeclineRematchMessage = Message<typeof declineRematchType, RematchPayload>
+
+export type RematchRequestedPayload = null
+
+export const rematchRequestedType = 'RematchRequested'
+export type RematchRequestedMessage = Message<typeof rematchRequestedType, RematchRequestedPayload>
+
+export const rematchOfferedType = 'RematchOffered'
+export type RematchOfferedMessage = Message<typeof rematchOfferedType, RematchPayload>
+
+export const rematchDeclinedType = 'RematchDeclined'
+export type RematchDeclinedMessage = Message<typeof rematchDeclinedType, RematchPayload>
+
+
+/*
+* Profile
+* */
+export type GetProfilePayload = protocol.GetProfilePayload | null
+
+export const getProfileType = 'GetProfile'
+export type GetProfileMessage = Message<typeof getProfileType, GetProfilePayload>
+
+export const profileType = 'Profile'
+export type ProfileMessage = Message<typeof profileType, ProfilePayload>
+
+
+/*
+* Leaderboard
+* */
+export type GetLeaderboardPayload = protocol.GetLeaderboardPayload | null
+
+export const getLeaderboardType = 'GetLeaderboard'
+export type GetLeaderboardMessage = Message<typeof getLeaderboardType, GetLeaderboardPayload>
+
+export const leaderboardType = 'Leaderboard'
+export type LeaderboardMessage = Message<typeof leaderboardType, LeaderboardPayload>
+
+
+/*
+* Server shutting down
+* */
+export const serverShuttingDownType = 'ServerShuttingDown'
+export type ServerShuttingDownMessage = Message<typeof serverShuttingDownType, ServerShuttingDownPayload>
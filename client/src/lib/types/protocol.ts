@@ -0,0 +1,288 @@
+// Generated from the server protocol types by `UPDATE_PROTOCOL=1 cargo test protocol_files`, do not edit.
+
+// Incoming message with an optional id chosen by the client.
+export type IncomingEnvelope = {
+    request_id?: string | null,
+} & ({
+    type: 'StartMatchmaking',
+    data: RoomConfig | null,
+} | {
+    type: 'CancelMatchmaking',
+} | {
+    type: 'CreatePrivateRoom',
+    data: RoomConfig | null,
+} | {
+    type: 'JoinPrivateRoom',
+    data: JoinPrivateRoomPayload,
+} | {
+    type: 'RequestRematch',
+    data: RematchPayload,
+} | {
+    type: 'DeclineRematch',
+    data: RematchPayload,
+} | {
+    type: 'MakeAction',
+    data: MakeActionPayload,
+} | {
+    type: 'GetProfile',
+    data: GetProfilePayload | null,
+} | {
+    type: 'GetLeaderboard',
+    data: GetLeaderboardPayload | null,
+});
+
+// Match format chosen by the players.
+export type RoomConfig = {
+    // Round wins needed to win the game, 2 is best-of-3
+    wins_required?: number,
+    // The game ends after this many rounds, the player with more wins is the winner
+    max_rounds?: number | null,
+    // Whether drawn rounds are counted toward `max_rounds`
+    count_draws?: boolean,
+    ruleset?: RulesetKind,
+};
+
+// Built-in rulesets a room can be played with.
+export enum RulesetKind {
+    Classic = 'Classic',
+    Rpsls = 'Rpsls',
+}
+
+export type JoinPrivateRoomPayload = {
+    code: string,
+};
+
+export type RematchPayload = {
+    // The finished room
+    room: string,
+};
+
+export type MakeActionPayload = {
+    room: string,
+    action: Action,
+};
+
+export enum Action {
+    Rock = 'Rock',
+    Paper = 'Paper',
+    Scissors = 'Scissors',
+    Lizard = 'Lizard',
+    Spock = 'Spock',
+}
+
+export type GetProfilePayload = {
+    // Defaults to the requesting user
+    user_id?: number | null,
+};
+
+export type GetLeaderboardPayload = {
+    scope?: LeaderboardScope,
+    period?: LeaderboardPeriod,
+    offset?: number,
+    // Capped by `MAX_LEADERBOARD_LIMIT`
+    limit?: number,
+};
+
+export enum LeaderboardScope {
+    Global = 'Global',
+    // The user and everyone they have played with
+    Friends = 'Friends',
+}
+
+export enum LeaderboardPeriod {
+    // Players are ranked by rating
+    AllTime = 'AllTime',
+    // Players are ranked by wins in the last 7 days
+    Weekly = 'Weekly',
+    // Players are ranked by wins in the last 24 hours
+    Daily = 'Daily',
+}
+
+// Outgoing message. A direct reply carries the id of the request, messages the server pushes on its own don't have one.
+export type OutgoingEnvelope = {
+    request_id?: string | null,
+} & ({
+    type: 'Error',
+    data: ErrorPayload,
+} | {
+    type: 'ConfirmConnect',
+    data: ConfirmConnectPayload,
+} | {
+    type: 'MatchmakingSuccess',
+    data: MatchmakingSuccessPayload,
+} | {
+    type: 'MatchmakingStarted',
+} | {
+    type: 'MatchmakingCancelled',
+} | {
+    type: 'MakeActionSuccess',
+} | {
+    type: 'RoundFinished',
+    data: RoundFinishedPayload,
+} | {
+    type: 'GameFinished',
+    data: GameFinishedPayload,
+} | {
+    type: 'ResumeSession',
+    data: RoomSnapshotPayload,
+} | {
+    type: 'RoundStarted',
+    data: RoundStartedPayload,
+} | {
+    type: 'PrivateRoomCreated',
+    data: PrivateRoomCreatedPayload,
+} | {
+    type: 'RematchRequested',
+} | {
+    type: 'RematchOffered',
+    data: RematchPayload,
+} | {
+    type: 'RematchDeclined',
+    data: RematchPayload,
+} | {
+    type: 'Profile',
+    data: ProfilePayload,
+} | {
+    type: 'Leaderboard',
+    data: LeaderboardPayload,
+} | {
+    type: 'ServerShuttingDown',
+    data: ServerShuttingDownPayload,
+});
+
+export type ErrorPayload = {
+    code: ErrorCode,
+    // Human-readable description, may change at any time
+    message: string,
+};
+
+// Machine-readable reason of an error sent to the client. Codes are part of the protocol: never rename or remove them, only add new ones.
+export enum ErrorCode {
+    InvalidConfig = 'InvalidConfig',
+    RoomNotFound = 'RoomNotFound',
+    NotInRoom = 'NotInRoom',
+    ActionNotAllowed = 'ActionNotAllowed',
+    ActionAlreadySubmitted = 'ActionAlreadySubmitted',
+    InviteCodeNotFound = 'InviteCodeNotFound',
+    InviteCodeExpired = 'InviteCodeExpired',
+    RoomFull = 'RoomFull',
+    CannotJoinOwnRoom = 'CannotJoinOwnRoom',
+    RematchUnavailable = 'RematchUnavailable',
+    Internal = 'Internal',
+    // The message couldn't be parsed
+    BadRequest = 'BadRequest',
+    // The other player is offline
+    OpponentGone = 'OpponentGone',
+    // The player is already in a running game
+    AlreadyInRoom = 'AlreadyInRoom',
+    // New games can't be started until the server is restarted
+    ServerShuttingDown = 'ServerShuttingDown',
+    // The client sends messages too fast, the message was dropped
+    RateLimited = 'RateLimited',
+}
+
+export type ConfirmConnectPayload = {
+    message: string,
+    // Version used for this connection
+    protocol_version: number,
+    supported_versions: number[],
+    // Optional features the server has
+    capabilities: string[],
+};
+
+export type MatchmakingSuccessPayload = {
+    room: string,
+    opponent: number,
+    config: RoomConfig,
+};
+
+export type RoundFinishedPayload = {
+    winner?: number | null,
+    actions: ActionHistory[],
+    next_round_count: number,
+};
+
+export type ActionHistory = {
+    user_id: number,
+    action: Action,
+};
+
+export type GameFinishedPayload = {
+    winner?: number | null,
+    actions: ActionHistory[],
+    reason: FinishReason,
+    // Rating change of the receiving player, absent if the game is abandoned or couldn't be saved
+    rating?: RatingChangePayload | null,
+};
+
+export enum FinishReason {
+    Completed = 'Completed',
+    Forfeit = 'Forfeit',
+    // Neither player made an action before the round deadline
+    Abandoned = 'Abandoned',
+}
+
+export type RatingChangePayload = {
+    // The new rating
+    rating: number,
+    delta: number,
+};
+
+export type RoomSnapshotPayload = {
+    room: string,
+    opponent: number,
+    rounds: RoundHistory[],
+    wins: number,
+    opponent_wins: number,
+    next_round_count: number,
+    my_action?: Action | null,
+    deadline: number,
+    config: RoomConfig,
+};
+
+export type RoundHistory = {
+    winner?: number | null,
+    actions: ActionHistory[],
+};
+
+export type RoundStartedPayload = {
+    room: string,
+    round: number,
+    // Unix time in milliseconds when the round is resolved without missing actions
+    deadline: number,
+};
+
+export type PrivateRoomCreatedPayload = {
+    // Invite code, can be shared as a `startapp` parameter of the mini app link
+    code: string,
+    // Unix time in milliseconds
+    expires_at: number,
+};
+
+export type ProfilePayload = {
+    user_id: number,
+    rating: number,
+    games: number,
+    wins: number,
+    losses: number,
+    draws: number,
+};
+
+export type LeaderboardPayload = {
+    entries: LeaderboardEntryPayload[],
+    // The requesting user, `None` until they are ranked
+    me?: LeaderboardEntryPayload | null,
+};
+
+export type LeaderboardEntryPayload = {
+    rank: number,
+    user_id: number,
+    display_name?: string | null,
+    rating: number,
+    wins: number,
+};
+
+export type ServerShuttingDownPayload = {
+    // Running games can be finished within this time, then the connection is closed
+    grace_seconds: number,
+};
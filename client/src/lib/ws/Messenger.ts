@@ -1,17 +1,28 @@
 import EventEmitter from "eventemitter3";
 import type { WsConnection } from "./WsConnection";
-import { startMatchmakingType, matchmakingStartedType, type MatchmakingStartedPayload, matchmakingSuccessType, type MatchmakingSuccessPayload, type MakeActionPayload, makeActionType, makeActionSuccessType, type MakeActionSuccessPayload, roundFinishedType, type RoundFinishedPayload, gameFinishedType, type GameFinishedPayload } from "../types/messages";
+import { errorType, type ErrorPayload, startMatchmakingType, type StartMatchmakingPayload, type CreatePrivateRoomPayload, cancelMatchmakingType, matchmakingStartedType, type MatchmakingStartedPayload, matchmakingCancelledType, type MatchmakingCancelledPayload, matchmakingSuccessType, type MatchmakingSuccessPayload, type MakeActionPayload, makeActionType, makeActionSuccessType, type MakeActionSuccessPayload, roundFinishedType, type RoundFinishedPayload, gameFinishedType, type GameFinishedPayload, resumeSessionType, type RoomSnapshotPayload, roundStartedType, type RoundStartedPayload, createPrivateRoomType, privateRoomCreatedType, type PrivateRoomCreatedPayload, joinPrivateRoomType, type JoinPrivateRoomPayload, type RematchPayload, requestRematchType, declineRematchType, rematchRequestedType, type RematchRequestedPayload, rematchOfferedType, rematchDeclinedType, getProfileType, type GetProfilePayload, profileType, type ProfilePayload, getLeaderboardType, type GetLeaderboardPayload, leaderboardType, type LeaderboardPayload, serverShuttingDownType, type ServerShuttingDownPayload } from "../types/messages";
 
 export type MessengerConfig = {
     connection: WsConnection;
 };
 
 type IncomingMessages = {
+    [errorType]: ErrorPayload;
     [matchmakingStartedType]: MatchmakingStartedPayload;
+    [matchmakingCancelledType]: MatchmakingCancelledPayload;
     [matchmakingSuccessType]: MatchmakingSuccessPayload;
     [makeActionSuccessType]: MakeActionSuccessPayload;
     [roundFinishedType]: RoundFinishedPayload;
     [gameFinishedType]: GameFinishedPayload;
+    [resumeSessionType]: RoomSnapshotPayload;
+    [roundStartedType]: RoundStartedPayload;
+    [privateRoomCreatedType]: PrivateRoomCreatedPayload;
+    [rematchRequestedType]: RematchRequestedPayload;
+    [rematchOfferedType]: RematchPayload;
+    [rematchDeclinedType]: RematchPayload;
+    [profileType]: ProfilePayload;
+    [leaderboardType]: LeaderboardPayload;
+    [serverShuttingDownType]: ServerShuttingDownPayload;
 };
 
 type IncomingMessageType = keyof IncomingMessages
@@ -25,9 +36,44 @@ export class Messenger extends EventEmitter {
         this.connection.on('message', this.handleMessage);
     }
 
-    sendStartMatchmaking() {
+    sendStartMatchmaking(data: StartMatchmakingPayload = null) {
         this.connection.sendJson({
             type: startMatchmakingType,
+            data,
+        })
+    }
+
+    sendCancelMatchmaking() {
+        this.connection.sendJson({
+            type: cancelMatchmakingType,
+        })
+    }
+
+    sendCreatePrivateRoom(data: CreatePrivateRoomPayload = null) {
+        this.connection.sendJson({
+            type: createPrivateRoomType,
+            data,
+        })
+    }
+
+    sendJoinPrivateRoom(data: JoinPrivateRoomPayload) {
+        this.connection.sendJson({
+            type: joinPrivateRoomType,
+            data,
+        })
+    }
+
+    sendRequestRematch(data: RematchPayload) {
+        this.connection.sendJson({
+            type: requestRematchType,
+            data,
+        })
+    }
+
+    sendDeclineRematch(data: RematchPayload) {
+        this.connection.sendJson({
+            type: declineRematchType,
+            data,
         })
     }
 
@@ -38,6 +84,20 @@ export class Messenger extends EventEmitter {
         })
     }
 
+    sendGetProfile(data: GetProfilePayload = null) {
+        this.connection.sendJson({
+            type: getProfileType,
+            data,
+        })
+    }
+
+    sendGetLeaderboard(data: GetLeaderboardPayload = null) {
+        this.connection.sendJson({
+            type: getLeaderboardType,
+            data,
+        })
+    }
+
     dispose() {
         this.connection.off('message', this.handleMessage);
     }
@@ -67,9 +127,15 @@ export class Messenger extends EventEmitter {
         const messageEvent = message.type;
 
         switch (messageEvent) {
+            case errorType:
+                this.emit(errorType, message.data);
+                break;
             case matchmakingStartedType:
                 this.emit(matchmakingStartedType, message.data);
                 break;
+            case matchmakingCancelledType:
+                this.emit(matchmakingCancelledType, message.data);
+                break;
             case matchmakingSuccessType:
                 this.emit(matchmakingSuccessType, message.data);
                 break;
@@ -82,6 +148,33 @@ export class Messenger extends EventEmitter {
             case gameFinishedType:
                 this.emit(gameFinishedType, message.data);
                 break;
+            case resumeSessionType:
+                this.emit(resumeSessionType, message.data);
+                break;
+            case roundStartedType:
+                this.emit(roundStartedType, message.data);
+                break;
+            case privateRoomCreatedType:
+                this.emit(privateRoomCreatedType, message.data);
+                break;
+            case rematchRequestedType:
+                this.emit(rematchRequestedType, message.data);
+                break;
+            case rematchOfferedType:
+                this.emit(rematchOfferedType, message.data);
+                break;
+            case rematchDeclinedType:
+                this.emit(rematchDeclinedType, message.data);
+                break;
+            case profileType:
+                this.emit(profileType, message.data);
+                break;
+            case leaderboardType:
+                this.emit(leaderboardType, message.data);
+                break;
+            case serverShuttingDownType:
+                this.emit(serverShuttingDownType, message.data);
+                break;
         }
     };
 }
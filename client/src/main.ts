@@ -2,13 +2,14 @@ import './app.css'
 import App from './App.svelte'
 import { Messenger } from './lib/ws/Messenger';
 import { WsConnection } from './lib/ws/WsConnection';
-import { parseQuery } from './stores';
+import { PROTOCOL_VERSION } from './lib/types/messages';
 
 
-let id = parseQuery(window.Telegram.WebApp.initData).user.id
+// @ts-ignore
+const initData = encodeURIComponent(window.Telegram.WebApp.initData)
 
 const connection = new WsConnection(
-    { url: `ws://127.0.0.1:8080/ws/${id}` },
+    { url: `ws://127.0.0.1:8080/ws?init_data=${initData}&protocol_version=${PROTOCOL_VERSION}` },
 );
 connection.connect()
 
@@ -0,0 +1,3 @@
+pub mod error;
+pub mod responses;
+pub mod routes;
@@ -0,0 +1,51 @@
+use actix::MailboxError;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use derive_more::{Display, Error};
+use serde::Serialize;
+
+use crate::storage::error::StorageError;
+
+#[derive(Debug, Display, Error)]
+pub enum ApiError {
+    #[display(fmt = "Not found")]
+    NotFound,
+    #[display(fmt = "Cursor is invalid")]
+    InvalidCursor,
+    #[display(fmt = "Internal error")]
+    Internal,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::InvalidCursor => StatusCode::BAD_REQUEST,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            message: self.to_string(),
+        })
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(value: StorageError) -> Self {
+        log::error!("Storage error: {}", value);
+        ApiError::Internal
+    }
+}
+
+impl From<MailboxError> for ApiError {
+    fn from(value: MailboxError) -> Self {
+        log::error!("Couldn't send message to storage: {}", value);
+        ApiError::Internal
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    room::{
+        actor::{Action, RoomConfig},
+        messages::FinishReason,
+    },
+    storage::repository::{GameRecord, GameSummary, MoveRecord, RoundRecord},
+    types::{RoomId, UserId},
+    websockets::client_messages::to_unix_millis,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GamesPageResponse {
+    /// The most recent games first
+    pub games: Vec<GameSummaryResponse>,
+    /// Pass it as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameSummaryResponse {
+    pub id: RoomId,
+    pub users: [UserId; 2],
+    pub winner: Option<UserId>,
+    pub reason: FinishReason,
+    pub config: RoomConfig,
+    /// Unix time in milliseconds
+    pub started_at: u64,
+    /// Unix time in milliseconds
+    pub finished_at: u64,
+}
+
+impl From<GameSummary> for GameSummaryResponse {
+    fn from(value: GameSummary) -> Self {
+        Self {
+            id: value.id,
+            users: value.users,
+            winner: value.winner,
+            reason: value.reason,
+            config: value.config,
+            started_at: to_unix_millis(value.started_at),
+            finished_at: to_unix_millis(value.finished_at),
+        }
+    }
+}
+
+/// Round-by-round replay of a game.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameReplayResponse {
+    #[serde(flatten)]
+    pub game: GameSummaryResponse,
+    pub rounds: Vec<RoundReplayResponse>,
+}
+
+impl From<GameRecord> for GameReplayResponse {
+    fn from(value: GameRecord) -> Self {
+        Self {
+            game: GameSummaryResponse::from(GameSummary::from(&value)),
+            rounds: value
+                .rounds
+                .into_iter()
+                .map(RoundReplayResponse::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoundReplayResponse {
+    pub number: u8,
+    pub winner: Option<UserId>,
+    /// Unix time in milliseconds
+    pub started_at: u64,
+    /// Unix time in milliseconds
+    pub finished_at: u64,
+    pub moves: Vec<MoveReplayResponse>,
+}
+
+impl From<RoundRecord> for RoundReplayResponse {
+    fn from(value: RoundRecord) -> Self {
+        Self {
+            number: value.number,
+            winner: value.winner,
+            started_at: to_unix_millis(value.started_at),
+            finished_at: to_unix_millis(value.finished_at),
+            moves: value
+                .moves
+                .into_iter()
+                .map(MoveReplayResponse::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MoveReplayResponse {
+    pub user_id: UserId,
+    pub action: Action,
+    /// Unix time in milliseconds
+    pub made_at: u64,
+}
+
+impl From<MoveRecord> for MoveReplayResponse {
+    fn from(value: MoveRecord) -> Self {
+        Self {
+            user_id: value.user_id,
+            action: value.action,
+            made_at: to_unix_millis(value.made_at),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use actix::Addr;
+use actix_web::{
+    get,
+    web::{self, Data, Json, Path, Query, ServiceConfig},
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::extractor::AuthenticatedUser,
+    storage::{
+        actor::Storage,
+        messages::{LoadGame, LoadGames, LoadProfile},
+        repository::GamesCursor,
+    },
+    types::{RoomId, UserId},
+    websockets::client_messages::ProfilePayload,
+};
+
+use super::{
+    error::ApiError,
+    responses::{GameReplayResponse, GameSummaryResponse, GamesPageResponse},
+};
+
+const DEFAULT_GAMES_LIMIT: u32 = 20;
+
+const MAX_GAMES_LIMIT: u32 = 100;
+
+/// Read-only endpoints under `/api`. They are authenticated the same way as the websocket.
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/api")
+            .service(get_profile)
+            .service(get_games)
+            .service(get_game),
+    );
+}
+
+#[derive(Deserialize)]
+pub struct GamesQuery {
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[get("/users/{id}/profile")]
+pub async fn get_profile(
+    user: AuthenticatedUser,
+    path: Path<UserId>,
+    storage: Data<Addr<Storage>>,
+) -> Result<Json<ProfilePayload>, ApiError> {
+    log::debug!("User {} requested profile of {}", user.0.id, path);
+
+    let profile = storage
+        .send(LoadProfile {
+            user_id: path.into_inner(),
+        })
+        .await??
+        .ok_or(ApiError::NotFound)?;
+
+    Ok(Json(ProfilePayload::from(profile)))
+}
+
+#[get("/users/{id}/games")]
+pub async fn get_games(
+    user: AuthenticatedUser,
+    path: Path<UserId>,
+    query: Query<GamesQuery>,
+    storage: Data<Addr<Storage>>,
+) -> Result<Json<GamesPageResponse>, ApiError> {
+    log::debug!("User {} requested games of {}", user.0.id, path);
+
+    let before = match &query.cursor {
+        Some(cursor) => Some(GamesCursor::decode(cursor).ok_or(ApiError::InvalidCursor)?),
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_GAMES_LIMIT)
+        .clamp(1, MAX_GAMES_LIMIT);
+
+    // One more game tells whether there is a next page
+    let mut games = storage
+        .send(LoadGames {
+            user_id: path.into_inner(),
+            before,
+            limit: limit + 1,
+        })
+        .await??;
+
+    let next_cursor = if games.len() > limit as usize {
+        games.truncate(limit as usize);
+        games.last().map(|game| GamesCursor::after(game).encode())
+    } else {
+        None
+    };
+
+    Ok(Json(GamesPageResponse {
+        games: games.into_iter().map(GameSummaryResponse::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[get("/games/{id}")]
+pub async fn get_game(
+    user: AuthenticatedUser,
+    path: Path<RoomId>,
+    storage: Data<Addr<Storage>>,
+) -> Result<Json<GameReplayResponse>, ApiError> {
+    log::debug!("User {} requested game {}", user.0.id, path);
+
+    let game = storage
+        .send(LoadGame {
+            id: path.into_inner(),
+        })
+        .await??
+        .ok_or(ApiError::NotFound)?;
+
+    Ok(Json(GameReplayResponse::from(game)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use actix::SyncArbiter;
+    use actix_web::{
+        http::{header, StatusCode},
+        test, App,
+    };
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        auth::init_data::InitDataValidator,
+        room::{actor::RoomConfig, messages::FinishReason},
+        storage::{
+            memory::InMemoryMatchRepository,
+            repository::{GameRecord, MatchRepository, Profile},
+        },
+    };
+
+    const BOT_TOKEN: &str = "123456:TEST-TOKEN";
+
+    fn authorization(validator: &InitDataValidator) -> String {
+        let auth_date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let init_data = validator.sign(&[
+            ("auth_date", auth_date.to_string()),
+            ("user", r#"{"id":1,"first_name":"Ann"}"#.to_owned()),
+        ]);
+        format!("tma {}", init_data)
+    }
+
+    #[actix_web::test]
+    async fn profile_of_unknown_user_is_not_found() {
+        let repository = Arc::new(InMemoryMatchRepository::new());
+        let game = GameRecord {
+            id: Uuid::new_v4(),
+            users: [1, 2],
+            winner: Some(1),
+            reason: FinishReason::Completed,
+            config: RoomConfig::default(),
+            started_at: SystemTime::now(),
+            finished_at: SystemTime::now(),
+            rounds: vec![],
+        };
+        repository
+            .save_game(&game, &[Profile::new(1), Profile::new(2)])
+            .unwrap();
+        let storage = SyncArbiter::start(1, {
+            let repository = repository.clone();
+            move || Storage::new(repository.clone())
+        });
+        let validator = InitDataValidator::new(BOT_TOKEN, Duration::from_secs(60));
+        let authorization = authorization(&validator);
+        let app = test::init_service(
+            App::new()
+                .configure(configure)
+                .app_data(Data::new(storage))
+                .app_data(Data::new(validator)),
+        )
+        .await;
+
+        for (user_id, status) in [(2, StatusCode::OK), (3, StatusCode::NOT_FOUND)] {
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/users/{}/profile", user_id))
+                .insert_header((header::AUTHORIZATION, authorization.as_str()))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+
+            assert_eq!(response.status(), status, "profile of user {}", user_id);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use actix_web::{http::StatusCode, ResponseError};
+use derive_more::{Display, Error};
+
+#[derive(Debug, Display, Error)]
+pub enum AuthError {
+    #[display(fmt = "initData is missing")]
+    Missing,
+    #[display(fmt = "initData is malformed")]
+    Malformed,
+    #[display(fmt = "initData has no hash")]
+    MissingHash,
+    #[display(fmt = "initData signature is invalid")]
+    InvalidSignature,
+    #[display(fmt = "initData is expired")]
+    Expired,
+    #[display(fmt = "initData has no valid user")]
+    MissingUser,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Malformed | AuthError::MissingHash | AuthError::MissingUser => {
+                StatusCode::BAD_REQUEST
+            }
+            AuthError::Missing | AuthError::InvalidSignature | AuthError::Expired => {
+                StatusCode::UNAUTHORIZED
+            }
+        }
+    }
+}
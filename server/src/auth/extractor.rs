@@ -0,0 +1,55 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, http::header, web::Data, FromRequest, HttpRequest};
+use serde::Deserialize;
+
+use super::{
+    error::AuthError,
+    init_data::{InitDataValidator, TelegramUser},
+};
+
+/// Scheme of the `Authorization` header used by Telegram Mini Apps.
+const AUTHORIZATION_SCHEME: &str = "tma ";
+
+#[derive(Deserialize)]
+struct InitDataQuery {
+    init_data: String,
+}
+
+/// A user authenticated with the Mini App `initData`, passed either in the
+/// `Authorization: tma <initData>` header or in the `init_data` query parameter.
+pub struct AuthenticatedUser(pub TelegramUser);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let validator = req
+            .app_data::<Data<InitDataValidator>>()
+            .expect("InitDataValidator must be registered in the app");
+
+        let init_data = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix(AUTHORIZATION_SCHEME))
+            .map(|init_data| init_data.to_owned())
+            .or_else(|| {
+                serde_urlencoded::from_str::<InitDataQuery>(req.query_string())
+                    .ok()
+                    .map(|query| query.init_data)
+            });
+
+        let user = init_data
+            .ok_or(AuthError::Missing)
+            .and_then(|init_data| validator.validate(&init_data))
+            .map(AuthenticatedUser)
+            .map_err(|err| {
+                log::warn!("Rejected request to {}: {}", req.path(), err);
+                err
+            });
+
+        ready(user)
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::types::UserId;
+
+use super::error::AuthError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key used by Telegram to derive the secret from the bot token.
+/// https://core.telegram.org/bots/webapps#validating-data-received-via-the-mini-app
+const WEB_APP_DATA_KEY: &[u8] = b"WebAppData";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TelegramUser {
+    pub id: UserId,
+    #[serde(default)]
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+}
+
+impl TelegramUser {
+    /// Full name of the user, or the username if the name is empty.
+    pub fn display_name(&self) -> String {
+        let name = match &self.last_name {
+            Some(last_name) => format!("{} {}", self.first_name, last_name),
+            None => self.first_name.clone(),
+        };
+
+        match (name.trim(), &self.username) {
+            ("", Some(username)) => username.clone(),
+            ("", None) => format!("Player {}", self.id),
+            (name, _) => name.to_owned(),
+        }
+    }
+}
+
+/// Verifies Telegram Mini App `initData` strings signed with the bot token.
+#[derive(Clone)]
+pub struct InitDataValidator {
+    secret_key: Vec<u8>,
+    max_age: Duration,
+}
+
+impl InitDataValidator {
+    pub fn new(bot_token: &str, max_age: Duration) -> Self {
+        let mut mac =
+            HmacSha256::new_from_slice(WEB_APP_DATA_KEY).expect("HMAC can take a key of any size");
+        mac.update(bot_token.as_bytes());
+
+        Self {
+            secret_key: mac.finalize().into_bytes().to_vec(),
+            max_age,
+        }
+    }
+
+    pub fn validate(&self, init_data: &str) -> Result<TelegramUser, AuthError> {
+        self.validate_at(init_data, SystemTime::now())
+    }
+
+    pub fn validate_at(&self, init_data: &str, now: SystemTime) -> Result<TelegramUser, AuthError> {
+        let mut fields = serde_urlencoded::from_str::<Vec<(String, String)>>(init_data)
+            .map_err(|_| AuthError::Malformed)?;
+
+        let hash_position = fields
+            .iter()
+            .position(|(key, _)| key == "hash")
+            .ok_or(AuthError::MissingHash)?;
+        let (_, hash) = fields.swap_remove(hash_position);
+        let hash = hex::decode(hash).map_err(|_| AuthError::InvalidSignature)?;
+
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let data_check_string = fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret_key).expect("HMAC can take a key of any size");
+        mac.update(data_check_string.as_bytes());
+        mac.verify_slice(&hash)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let auth_date = fields
+            .iter()
+            .find(|(key, _)| key == "auth_date")
+            .and_then(|(_, value)| value.parse::<u64>().ok())
+            .ok_or(AuthError::Malformed)?;
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| AuthError::Expired)?
+            .as_secs();
+
+        if now.saturating_sub(auth_date) > self.max_age.as_secs() {
+            return Err(AuthError::Expired);
+        }
+
+        fields
+            .iter()
+            .find(|(key, _)| key == "user")
+            .and_then(|(_, value)| serde_json::from_str::<TelegramUser>(value).ok())
+            .ok_or(AuthError::MissingUser)
+    }
+
+    /// Signs the fields the way Telegram does and appends the hash.
+    #[cfg(test)]
+    pub fn sign(&self, fields: &[(&str, String)]) -> String {
+        let mut sorted = fields.to_vec();
+        sorted.sort();
+        let data_check_string = sorted
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let mut mac = HmacSha256::new_from_slice(&self.secret_key).unwrap();
+        mac.update(data_check_string.as_bytes());
+        let hash = hex::encode(mac.finalize().into_bytes());
+
+        let mut fields = fields.to_vec();
+        fields.push(("hash", hash));
+        serde_urlencoded::to_string(fields).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT_TOKEN: &str = "123456:TEST-TOKEN";
+    const AUTH_DATE: u64 = 1_700_000_000;
+    const USER: &str = r#"{"id":42,"first_name":"Ann","username":"ann"}"#;
+
+    fn validator() -> InitDataValidator {
+        InitDataValidator::new(BOT_TOKEN, Duration::from_secs(60))
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn signed(fields: &[(&str, String)]) -> String {
+        validator().sign(fields)
+    }
+
+    fn default_fields() -> Vec<(&'static str, String)> {
+        vec![
+            ("auth_date", AUTH_DATE.to_string()),
+            ("query_id", "AAA".to_owned()),
+            ("user", USER.to_owned()),
+        ]
+    }
+
+    #[test]
+    fn accepts_signed_payload() {
+        let user = validator()
+            .validate_at(&signed(&default_fields()), at(AUTH_DATE + 10))
+            .unwrap();
+
+        assert_eq!(user.id, 42);
+        assert_eq!(user.display_name(), "Ann");
+    }
+
+    #[test]
+    fn rejects_changed_field() {
+        let init_data = signed(&default_fields()).replace("AAA", "BBB");
+
+        assert!(matches!(
+            validator().validate_at(&init_data, at(AUTH_DATE)),
+            Err(AuthError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_bot_token() {
+        let other = InitDataValidator::new("654321:OTHER", Duration::from_secs(60));
+
+        assert!(matches!(
+            other.validate_at(&signed(&default_fields()), at(AUTH_DATE)),
+            Err(AuthError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_hex_hash() {
+        let init_data = format!("auth_date={}&user=%7B%7D&hash=not-hex", AUTH_DATE);
+
+        assert!(matches!(
+            validator().validate_at(&init_data, at(AUTH_DATE)),
+            Err(AuthError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_hash() {
+        let init_data = format!("auth_date={}&query_id=AAA", AUTH_DATE);
+
+        assert!(matches!(
+            validator().validate_at(&init_data, at(AUTH_DATE)),
+            Err(AuthError::MissingHash)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_payload() {
+        assert!(matches!(
+            validator().validate_at(&signed(&default_fields()), at(AUTH_DATE + 61)),
+            Err(AuthError::Expired)
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_user() {
+        let mut fields = default_fields();
+        fields.retain(|(key, _)| *key != "user");
+
+        assert!(matches!(
+            validator().validate_at(&signed(&fields), at(AUTH_DATE)),
+            Err(AuthError::MissingUser)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_user() {
+        let mut fields = default_fields();
+        fields[2].1 = r#"{"first_name":"Ann"}"#.to_owned();
+
+        assert!(matches!(
+            validator().validate_at(&signed(&fields), at(AUTH_DATE)),
+            Err(AuthError::MissingUser)
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+use derive_more::{Display, Error};
+
+#[derive(Debug, Display, Error)]
+pub enum ConfigError {
+    #[display(fmt = "Couldn't read config file {}: {}", path, source)]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[display(fmt = "Couldn't parse config: {}", _0)]
+    Parse(toml::de::Error),
+    #[display(fmt = "Invalid environment variable {}: {}", name, reason)]
+    Env {
+        name: String,
+        #[error(not(source))]
+        reason: String,
+    },
+    #[display(fmt = "Invalid config: {} {}", field, reason)]
+    Invalid {
+        field: &'static str,
+        #[error(not(source))]
+        reason: String,
+    },
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl ConfigError {
+    pub fn invalid(field: &'static str, reason: impl Into<String>) -> Self {
+        Self::Invalid {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
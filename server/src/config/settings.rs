@@ -0,0 +1,540 @@
+use std::{collections::HashMap, env, fs, io::ErrorKind, time::Duration};
+
+use serde::{Deserialize, Deserializer};
+use toml::{Table, Value};
+
+use crate::{room::actor::RoomSettings, websockets::client_messages::IncomingClientMessage};
+
+use super::error::ConfigError;
+
+/// Config file read when `CONFIG_PATH` is not set. Unlike a file given in `CONFIG_PATH` it may be missing.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Prefix of variables overriding the file, `RPS_HTTP_PORT` sets `port` in the `[http]` section.
+const ENV_PREFIX: &str = "RPS_";
+
+/// Variables the server was configured with before the config file, they are overridden by `RPS_` ones.
+const LEGACY_ENV: &[(&str, &str, &str)] = &[("BOT_TOKEN", "telegram", "bot_token")];
+
+/// Settings that can be overridden with `RPS_<SECTION>_<KEY>`. Tables, e.g. rate limits, are read from the file only.
+const ENV_SETTINGS: &[(&str, &str, EnvValue)] = &[
+    ("http", "bind", EnvValue::String),
+    ("http", "port", EnvValue::Integer),
+    ("tls", "cert_path", EnvValue::String),
+    ("tls", "key_path", EnvValue::String),
+    ("tls", "reload_interval_secs", EnvValue::Integer),
+    ("tls", "redirect_port", EnvValue::Integer),
+    ("telegram", "bot_token", EnvValue::String),
+    ("telegram", "init_data_max_age_secs", EnvValue::Integer),
+    ("storage", "database_path", EnvValue::String),
+    ("websocket", "ping_interval_secs", EnvValue::Integer),
+    ("websocket", "timeout_secs", EnvValue::Integer),
+    ("websocket", "max_frame_size", EnvValue::Integer),
+    ("websocket", "silent_violations", EnvValue::Integer),
+    ("websocket", "max_violations", EnvValue::Integer),
+    ("websocket", "violation_window_secs", EnvValue::Integer),
+    ("matchmaking", "interval_secs", EnvValue::Integer),
+    ("matchmaking", "initial_rating_band", EnvValue::Integer),
+    (
+        "matchmaking",
+        "rating_band_growth_per_second",
+        EnvValue::Integer,
+    ),
+    ("room", "disconnect_grace_secs", EnvValue::Integer),
+    ("room", "round_timeout_secs", EnvValue::Integer),
+    ("room", "timeout_policy", EnvValue::String),
+    ("server", "invite_code_ttl_secs", EnvValue::Integer),
+    ("server", "rematch_window_secs", EnvValue::Integer),
+    ("server", "shutdown_grace_secs", EnvValue::Integer),
+];
+
+/// Type of a setting given in an environment variable, the value is converted to it instead of being guessed.
+#[derive(Debug, Clone, Copy)]
+enum EnvValue {
+    String,
+    Integer,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub http: HttpConfig,
+    pub tls: TlsConfig,
+    pub telegram: TelegramConfig,
+    pub storage: StorageConfig,
+    pub websocket: WebsocketConfig,
+    pub matchmaking: MatchmakingConfig,
+    pub room: RoomSettings,
+    pub server: ServerConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub bind: String,
+    pub port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: "::".to_owned(),
+            port: 8080,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, HTTPS is served on `http.port` when it's set together with the key
+    pub cert_path: Option<String>,
+    /// PEM private key of the certificate
+    pub key_path: Option<String>,
+    /// How often the files are checked for a renewed certificate
+    #[serde(rename = "reload_interval_secs", deserialize_with = "seconds")]
+    pub reload_interval: Duration,
+    /// Plain HTTP port redirecting every request to HTTPS
+    pub redirect_port: Option<u16>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: None,
+            key_path: None,
+            reload_interval: Duration::from_secs(60),
+            redirect_port: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Certificate and key paths if TLS is enabled.
+    pub fn paths(&self) -> Option<(&str, &str)> {
+        Some((self.cert_path.as_deref()?, self.key_path.as_deref()?))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    /// How long a signed `initData` is accepted
+    #[serde(rename = "init_data_max_age_secs", deserialize_with = "seconds")]
+    pub init_data_max_age: Duration,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            bot_token: String::new(),
+            init_data_max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// SQLite database, match history is kept in memory only without it
+    pub database_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebsocketConfig {
+    #[serde(rename = "ping_interval_secs", deserialize_with = "seconds")]
+    pub ping_interval: Duration,
+    /// A connection without a pong for this long is closed
+    #[serde(rename = "timeout_secs", deserialize_with = "seconds")]
+    pub timeout: Duration,
+    /// Larger frames close the connection
+    pub max_frame_size: usize,
+    /// Limit of every frame of a connection, unparsable ones included
+    pub rate_limit: BucketConfig,
+    /// Limits of message types, a message has to fit both its type's limit and `rate_limit`
+    pub message_rate_limits: HashMap<String, BucketConfig>,
+    /// How many limited messages in a row are dropped silently before they're answered with `RateLimited`
+    pub silent_violations: u32,
+    /// The connection is closed with the policy violation code after this many limited messages in a row
+    pub max_violations: u32,
+    /// Limited messages are counted in a row until there's none for this long
+    #[serde(rename = "violation_window_secs", deserialize_with = "seconds")]
+    pub violation_window: Duration,
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+            max_frame_size: 4096,
+            rate_limit: BucketConfig::new(20, 10.0),
+            message_rate_limits: HashMap::from([
+                ("StartMatchmaking".to_owned(), BucketConfig::new(5, 1.0)),
+                ("CreatePrivateRoom".to_owned(), BucketConfig::new(3, 0.2)),
+                ("JoinPrivateRoom".to_owned(), BucketConfig::new(5, 1.0)),
+                ("MakeAction".to_owned(), BucketConfig::new(5, 2.0)),
+            ]),
+            silent_violations: 5,
+            max_violations: 20,
+            violation_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Token bucket: up to `burst` messages at once, then `per_second` on average.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl BucketConfig {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.burst > 0 && self.per_second > 0.0
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchmakingConfig {
+    /// How often queued players are matched again with their widened rating bands
+    #[serde(rename = "interval_secs", deserialize_with = "seconds")]
+    pub interval: Duration,
+    /// Allowed rating difference for a player who has just joined the queue
+    pub initial_rating_band: u32,
+    /// How much the allowed rating difference grows every second in the queue
+    pub rating_band_growth_per_second: u32,
+}
+
+impl Default for MatchmakingConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            initial_rating_band: 100,
+            rating_band_growth_per_second: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// How long an invite code to a private room can be used
+    #[serde(rename = "invite_code_ttl_secs", deserialize_with = "seconds")]
+    pub invite_code_ttl: Duration,
+    /// How long both players have to agree on a rematch after the game is finished
+    #[serde(rename = "rematch_window_secs", deserialize_with = "seconds")]
+    pub rematch_window: Duration,
+    /// How long running games can take after a shutdown signal
+    #[serde(rename = "shutdown_grace_secs", deserialize_with = "seconds")]
+    pub shutdown_grace: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            invite_code_ttl: Duration::from_secs(10 * 60),
+            rematch_window: Duration::from_secs(30),
+            shutdown_grace: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file, applies environment variables on top of it and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, is_required) = match env::var("CONFIG_PATH") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_owned(), false),
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if !is_required && err.kind() == ErrorKind::NotFound => String::new(),
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+
+        let mut table = toml::from_str::<Table>(&content)?;
+        apply_env(&mut table, env::vars())?;
+
+        let config = Value::Table(table).try_into::<Config>()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.telegram.bot_token.is_empty() {
+            return Err(ConfigError::invalid(
+                "telegram.bot_token",
+                "is required, set it in the config file or in BOT_TOKEN",
+            ));
+        }
+
+        if self.http.port == 0 {
+            return Err(ConfigError::invalid("http.port", "must not be 0"));
+        }
+
+        match (&self.tls.cert_path, &self.tls.key_path) {
+            (Some(_), None) => {
+                return Err(ConfigError::invalid(
+                    "tls.key_path",
+                    "is required when tls.cert_path is set",
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::invalid(
+                    "tls.cert_path",
+                    "is required when tls.key_path is set",
+                ))
+            }
+            _ => (),
+        }
+
+        if self.tls.reload_interval.is_zero() {
+            return Err(ConfigError::invalid(
+                "tls.reload_interval_secs",
+                "must be positive",
+            ));
+        }
+
+        if let Some(port) = self.tls.redirect_port {
+            if self.tls.paths().is_none() {
+                return Err(ConfigError::invalid(
+                    "tls.redirect_port",
+                    "requires tls.cert_path and tls.key_path",
+                ));
+            }
+            if port == 0 || port == self.http.port {
+                return Err(ConfigError::invalid(
+                    "tls.redirect_port",
+                    "must be a non-zero port different from http.port",
+                ));
+            }
+        }
+
+        if self.websocket.ping_interval.is_zero() {
+            return Err(ConfigError::invalid(
+                "websocket.ping_interval_secs",
+                "must be positive",
+            ));
+        }
+
+        if self.websocket.timeout <= self.websocket.ping_interval {
+            return Err(ConfigError::invalid(
+                "websocket.timeout_secs",
+                "must be greater than websocket.ping_interval_secs",
+            ));
+        }
+
+        if self.websocket.max_frame_size == 0 {
+            return Err(ConfigError::invalid(
+                "websocket.max_frame_size",
+                "must be positive",
+            ));
+        }
+
+        if !self.websocket.rate_limit.is_valid() {
+            return Err(ConfigError::invalid(
+                "websocket.rate_limit",
+                "must have a positive burst and per_second",
+            ));
+        }
+
+        for (message_type, bucket) in &self.websocket.message_rate_limits {
+            if !IncomingClientMessage::TYPES.contains(&message_type.as_str()) {
+                return Err(ConfigError::invalid(
+                    "websocket.message_rate_limits",
+                    format!("has an unknown message type {}", message_type),
+                ));
+            }
+            if !bucket.is_valid() {
+                return Err(ConfigError::invalid(
+                    "websocket.message_rate_limits",
+                    format!("{} must have a positive burst and per_second", message_type),
+                ));
+            }
+        }
+
+        if self.websocket.max_violations <= self.websocket.silent_violations {
+            return Err(ConfigError::invalid(
+                "websocket.max_violations",
+                "must be greater than websocket.silent_violations",
+            ));
+        }
+
+        if self.matchmaking.interval.is_zero() {
+            return Err(ConfigError::invalid(
+                "matchmaking.interval_secs",
+                "must be positive",
+            ));
+        }
+
+        if self.room.round_timeout.is_zero() {
+            return Err(ConfigError::invalid(
+                "room.round_timeout_secs",
+                "must be positive",
+            ));
+        }
+
+        if let Err(err) = self.room.default_config.validate() {
+            return Err(ConfigError::invalid("room.default_config", err.message));
+        }
+
+        if self.server.invite_code_ttl.is_zero() {
+            return Err(ConfigError::invalid(
+                "server.invite_code_ttl_secs",
+                "must be positive",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the settings given in the legacy and `RPS_` variables. Unknown `RPS_` variables are ignored.
+fn apply_env(
+    table: &mut Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), ConfigError> {
+    let vars = vars.into_iter().collect::<HashMap<String, String>>();
+
+    for (name, section, key) in LEGACY_ENV {
+        if let Some(value) = vars.get(*name) {
+            set(table, section, key, Value::String(value.clone()));
+        }
+    }
+
+    for (name, value) in &vars {
+        let Some(setting) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let Some((section, key, env_value)) = ENV_SETTINGS.iter().find(|(section, key, _)| {
+            setting.eq_ignore_ascii_case(&format!("{}_{}", section, key))
+        }) else {
+            log::warn!("{} is not a known setting, it's ignored", name);
+            continue;
+        };
+
+        let value = match env_value {
+            EnvValue::String => Value::String(value.clone()),
+            EnvValue::Integer => {
+                value
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| ConfigError::Env {
+                        name: name.clone(),
+                        reason: format!("{} is not an integer", value),
+                    })?
+            }
+        };
+        set(table, section, key, value);
+    }
+
+    Ok(())
+}
+
+fn set(table: &mut Table, section: &str, key: &str, value: Value) {
+    let section = table
+        .entry(section)
+        .or_insert_with(|| Value::Table(Table::new()));
+    if let Value::Table(section) = section {
+        section.insert(key.to_owned(), value);
+    }
+}
+
+/// Reads a duration given in whole seconds.
+pub fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::actor::TimeoutPolicy;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn config(env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let mut table = Table::new();
+        apply_env(&mut table, vars(env))?;
+        Ok(Value::Table(table).try_into::<Config>()?)
+    }
+
+    #[test]
+    fn env_values_take_the_type_of_the_setting() {
+        let config = config(&[
+            ("RPS_HTTP_PORT", "9000"),
+            ("RPS_STORAGE_DATABASE_PATH", "2024"),
+            ("RPS_TELEGRAM_BOT_TOKEN", "123"),
+            ("RPS_ROOM_TIMEOUT_POLICY", "RandomAction"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.http.port, 9000);
+        assert_eq!(config.storage.database_path.as_deref(), Some("2024"));
+        assert_eq!(config.telegram.bot_token, "123");
+        assert!(matches!(
+            config.room.timeout_policy,
+            TimeoutPolicy::RandomAction
+        ));
+    }
+
+    #[test]
+    fn rps_variables_override_legacy_ones() {
+        let config = config(&[("BOT_TOKEN", "1"), ("RPS_TELEGRAM_BOT_TOKEN", "2")]).unwrap();
+
+        assert_eq!(config.telegram.bot_token, "2");
+    }
+
+    #[test]
+    fn unknown_env_settings_are_ignored() {
+        let config = config(&[
+            ("RPS_HTTP_PORTS", "9000"),
+            ("RPS_WEBSOCKET_RATE_LIMIT", "1"),
+            ("RPS_DEPLOYMENT", "staging"),
+            ("HTTP_PORT", "9000"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.http.port, HttpConfig::default().port);
+    }
+
+    #[test]
+    fn invalid_integer_is_rejected() {
+        let err = config(&[("RPS_HTTP_PORT", "http")]).unwrap_err();
+
+        assert!(matches!(err, ConfigError::Env { name, .. } if name == "RPS_HTTP_PORT"));
+    }
+
+    #[test]
+    fn every_env_setting_is_a_config_field() {
+        for (section, key, env_value) in ENV_SETTINGS {
+            // A timeout policy is a valid value of the other string settings as well
+            let value = match env_value {
+                EnvValue::String => "LoseRound",
+                EnvValue::Integer => "1",
+            };
+            let name = format!("{}{}_{}", ENV_PREFIX, section, key).to_uppercase();
+
+            let mut table = Table::new();
+            apply_env(&mut table, [(name.clone(), value.to_owned())]).unwrap();
+            assert!(table.get(*section).is_some(), "{} isn't applied", name);
+            if let Err(err) = Value::Table(table).try_into::<Config>() {
+                panic!("{} isn't a setting: {}", name, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod responses;
+pub mod routes;
@@ -0,0 +1,22 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub server: CheckResponse,
+    pub storage: CheckResponse,
+}
+
+#[derive(Serialize)]
+pub struct CheckResponse {
+    pub ok: bool,
+    /// Time to get the reply from the actor
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use actix::Addr;
+use actix_web::{
+    get,
+    web::{Data, ServiceConfig},
+    HttpResponse,
+};
+
+use crate::{
+    server::{actor::Server, messages::GetStatus},
+    storage::{actor::Storage, messages::CheckStorage},
+};
+
+use super::responses::{CheckResponse, HealthResponse, ReadinessResponse};
+
+/// How long an actor has to answer before it's considered stuck.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Unauthenticated endpoints for the orchestrator.
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(healthz).service(readyz);
+}
+
+/// The process is up and serves HTTP.
+#[get("/healthz")]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse { status: "ok" })
+}
+
+/// The server actor and the storage answer in time, and the server isn't shutting down.
+#[get("/readyz")]
+pub async fn readyz(server: Data<Addr<Server>>, storage: Data<Addr<Storage>>) -> HttpResponse {
+    let started_at = Instant::now();
+    let server = match server.send(GetStatus).timeout(CHECK_TIMEOUT).await {
+        Ok(status) if status.shutting_down => check(started_at, Some("Shutting down".to_owned())),
+        Ok(_) => check(started_at, None),
+        Err(err) => check(started_at, Some(err.to_string())),
+    };
+
+    let started_at = Instant::now();
+    let storage = match storage.send(CheckStorage).timeout(CHECK_TIMEOUT).await {
+        Ok(Ok(())) => check(started_at, None),
+        Ok(Err(err)) => check(started_at, Some(err.to_string())),
+        Err(err) => check(started_at, Some(err.to_string())),
+    };
+
+    let response = ReadinessResponse {
+        ready: server.ok && storage.ok,
+        server,
+        storage,
+    };
+
+    if response.ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        log::warn!("Server isn't ready");
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+fn check(started_at: Instant, error: Option<String>) -> CheckResponse {
+    CheckResponse {
+        ok: error.is_none(),
+        latency_ms: started_at.elapsed().as_millis() as u64,
+        error,
+    }
+}
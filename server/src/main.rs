@@ -1,45 +1,226 @@
-use actix::{Actor, Addr};
-use actix_web::{web::{Payload, Data, Path}, Error, HttpRequest, HttpResponse, get, HttpServer, App};
+use std::{future::poll_fn, sync::Arc, task::Poll};
+
+use actix::{Actor, Addr, SyncArbiter};
+use actix_web::{
+    get,
+    rt::signal::unix::{signal, SignalKind},
+    web::{self, Data, Payload, Query},
+    App, Error, HttpRequest, HttpResponse, HttpServer,
+};
 use actix_web_actors::ws;
-use server::actor::Server;
-use types::UserId;
+use auth::{extractor::AuthenticatedUser, init_data::InitDataValidator};
+use config::settings::{Config, WebsocketConfig};
+use metrics::registry::Metrics;
+use serde::Deserialize;
+use server::{actor::Server, messages::Shutdown};
+use storage::{
+    actor::Storage, memory::InMemoryMatchRepository, repository::MatchRepository,
+    sqlite::SqliteMatchRepository,
+};
+use tls::{
+    certificates::CertificateResolver,
+    redirect::{redirect_to_https, HttpsPort},
+};
 
-use crate::websockets::ws::Connection;
+use crate::websockets::{
+    protocol::{Encoding, DEFAULT_PROTOCOL_VERSION},
+    ws::Connection,
+};
 
-mod websockets;
+mod api;
+mod auth;
+mod config;
+mod health;
+mod metrics;
+mod rating;
+mod room;
 mod server;
+mod storage;
+mod tls;
 mod types;
-mod room;
+mod websockets;
+
+#[derive(Deserialize)]
+pub struct ConnectionQuery {
+    /// Version of the protocol the client speaks
+    protocol_version: Option<u16>,
+    #[serde(default)]
+    encoding: Encoding,
+}
 
-#[get("/ws/{user_id}")]
+#[get("/ws")]
 pub async fn start_connection(
     req: HttpRequest,
     stream: Payload,
-    user_id: Path<UserId>,
+    user: AuthenticatedUser,
+    query: Query<ConnectionQuery>,
     srv: Data<Addr<Server>>,
+    metrics: Data<Metrics>,
+    config: Data<WebsocketConfig>,
 ) -> Result<HttpResponse, Error> {
+    let AuthenticatedUser(user) = user;
     let conn = Connection::new(
-        *user_id,
+        user.id,
+        user.display_name(),
+        query.protocol_version.unwrap_or(DEFAULT_PROTOCOL_VERSION),
+        query.encoding,
         srv.get_ref().clone(),
+        metrics.get_ref().clone(),
+        config.get_ref().clone(),
     );
 
-    let resp = ws::start(conn, &req, stream)?;
+    let resp = ws::WsResponseBuilder::new(conn, &req, stream)
+        .frame_size(config.max_frame_size)
+        .start()?;
     Ok(resp)
 }
 
+/// Resolves on SIGTERM or SIGINT.
+async fn shutdown_signal() -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    poll_fn(|cx| {
+        if terminate.poll_recv(cx).is_ready() || interrupt.poll_recv(cx).is_ready() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     pretty_env_logger::init();
+
     log::info!("Starting server...");
 
-    let server = Server::new().start();
+    let config = Config::load().map_err(|err| {
+        log::error!("{}", err);
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+    })?;
+
+    let validator = InitDataValidator::new(
+        &config.telegram.bot_token,
+        config.telegram.init_data_max_age,
+    );
+
+    let repository: Arc<dyn MatchRepository> = match &config.storage.database_path {
+        Some(path) => {
+            let repository = SqliteMatchRepository::open(path).map_err(std::io::Error::other)?;
+            repository.migrate().map_err(std::io::Error::other)?;
+            Arc::new(repository)
+        }
+        None => {
+            log::warn!("Database path is not set, match history is kept in memory only");
+            Arc::new(InMemoryMatchRepository::new())
+        }
+    };
+    let metrics = Metrics::new().map_err(std::io::Error::other)?;
+
+    let storage = SyncArbiter::start(1, move || Storage::new(repository.clone()));
+
+    let server = Server::new(
+        config.server,
+        config.matchmaking,
+        config.room,
+        storage.clone(),
+        metrics.clone(),
+    )
+    .start();
+
+    let tls_config = match config.tls.paths() {
+        Some((cert_path, key_path)) => {
+            let resolver = CertificateResolver::load(cert_path, key_path)
+                .map(Arc::new)
+                .map_err(std::io::Error::other)?;
+            let tls_config = resolver.server_config().map_err(std::io::Error::other)?;
+            actix_web::rt::spawn(resolver.watch(config.tls.reload_interval));
+            Some(tls_config)
+        }
+        None => None,
+    };
+
+    let redirect_server = match config.tls.redirect_port {
+        Some(port) => {
+            let https_port = HttpsPort(config.http.port);
+            let redirect_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(Data::new(https_port))
+                    .default_service(web::to(redirect_to_https))
+            })
+            .workers(1)
+            .bind((config.http.bind.as_str(), port))?
+            .disable_signals()
+            .run();
+            let handle = redirect_server.handle();
+            actix_web::rt::spawn(redirect_server);
+            Some(handle)
+        }
+        None => None,
+    };
+
+    let websocket_config = Data::new(config.websocket.clone());
+    let http_server = HttpServer::new({
+        let server = server.clone();
+        move || {
+            App::new()
+                .service(start_connection)
+                .service(metrics::routes::get_metrics)
+                .configure(api::routes::configure)
+                .configure(health::routes::configure)
+                .app_data(Data::new(server.clone()))
+                .app_data(Data::new(storage.clone()))
+                .app_data(Data::new(validator.clone()))
+                .app_data(Data::new(metrics.clone()))
+                .app_data(websocket_config.clone())
+        }
+    });
+    let address = (config.http.bind.as_str(), config.http.port);
+    let http_server = match tls_config {
+        Some(tls_config) => http_server.bind_rustls_0_23(address, tls_config)?,
+        None => http_server.bind(address)?,
+    }
+    // Signals are handled below, so running games are drained before the server stops
+    .disable_signals()
+    .run();
+
+    let handle = http_server.handle();
+    actix_web::rt::spawn(async move {
+        if let Err(err) = shutdown_signal().await {
+            log::error!("Couldn't listen for shutdown signals: {}", err);
+            return;
+        }
+
+        log::info!("Shutdown signal received, send it again to stop immediately");
+        actix_web::rt::spawn({
+            let handle = handle.clone();
+            async move {
+                if let Err(err) = server
+                    .send(Shutdown {
+                        grace: config.server.shutdown_grace,
+                    })
+                    .await
+                {
+                    log::error!("Couldn't send message to server: {}", err);
+                }
+                handle.stop(true).await;
+            }
+        });
+
+        if shutdown_signal().await.is_ok() {
+            log::warn!("Stopping without waiting for running games");
+            handle.stop(false).await;
+        }
+    });
+
+    http_server.await?;
+    if let Some(handle) = redirect_server {
+        handle.stop(true).await;
+    }
 
-    HttpServer::new(move ||
-        App::new()
-            .service(start_connection)
-            .app_data(Data::new(server.clone()))
-        )
-        .bind(("::", 8080))?
-        .run()
-        .await
+    Ok(())
 }
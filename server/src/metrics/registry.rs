@@ -0,0 +1,126 @@
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+
+/// Prometheus metrics of the server. Cloned into every actor, updating a metric is an atomic operation
+/// so actors never wait for each other or for a scrape.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub connections: IntGauge,
+    /// Sampled by the server every time it matches the queue
+    pub queue_length: IntGauge,
+    pub rooms: IntGauge,
+    pub games_started: IntCounter,
+    /// Every finished game, forfeited ones included
+    pub games_finished: IntCounter,
+    pub games_forfeited: IntCounter,
+    /// Frames which couldn't be parsed and websocket protocol errors
+    pub protocol_errors: IntCounter,
+    /// Messages over the rate limits by their type, `unparsed` for frames which couldn't be parsed
+    pub rate_limited_messages: IntCounterVec,
+    /// Connections closed for breaking the rate limits or sending a too large frame
+    pub policy_disconnects: IntCounter,
+    pub matchmaking_wait: Histogram,
+    pub round_duration: Histogram,
+    /// Time from sending `ProcessClientMessage` to getting the result, mailbox included
+    pub message_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("rps".to_owned()), None)?;
+
+        let metrics = Self {
+            connections: IntGauge::new("connections", "Active websocket connections")?,
+            queue_length: IntGauge::new("matchmaking_queue_length", "Players in the queue")?,
+            rooms: IntGauge::new("rooms", "Rooms with a game in progress")?,
+            games_started: IntCounter::new("games_started_total", "Started games")?,
+            games_finished: IntCounter::new("games_finished_total", "Finished games")?,
+            games_forfeited: IntCounter::new(
+                "games_forfeited_total",
+                "Games finished by a forfeit",
+            )?,
+            protocol_errors: IntCounter::new(
+                "protocol_errors_total",
+                "Messages which couldn't be processed",
+            )?,
+            rate_limited_messages: IntCounterVec::new(
+                Opts::new(
+                    "rate_limited_messages_total",
+                    "Messages over the rate limits of a connection",
+                ),
+                &["type"],
+            )?,
+            policy_disconnects: IntCounter::new(
+                "policy_disconnects_total",
+                "Connections closed for flooding",
+            )?,
+            matchmaking_wait: Histogram::with_opts(
+                HistogramOpts::from(Opts::new(
+                    "matchmaking_wait_seconds",
+                    "Time a player spent in the queue before a match",
+                ))
+                .buckets(exponential_buckets(0.5, 2.0, 10)?),
+            )?,
+            round_duration: Histogram::with_opts(
+                HistogramOpts::from(Opts::new("round_duration_seconds", "Duration of a round"))
+                    .buckets(exponential_buckets(0.5, 2.0, 8)?),
+            )?,
+            message_latency: Histogram::with_opts(
+                HistogramOpts::from(Opts::new(
+                    "process_client_message_seconds",
+                    "Time to process a client message by the server",
+                ))
+                .buckets(exponential_buckets(0.0005, 2.0, 12)?),
+            )?,
+            registry,
+        };
+
+        metrics
+            .registry
+            .register(Box::new(metrics.connections.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.queue_length.clone()))?;
+        metrics.registry.register(Box::new(metrics.rooms.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.games_started.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.games_finished.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.games_forfeited.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.protocol_errors.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.rate_limited_messages.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.policy_disconnects.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.matchmaking_wait.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.round_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.message_latency.clone()))?;
+
+        Ok(metrics)
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        String::from_utf8(buffer).map_err(|err| prometheus::Error::Msg(err.to_string()))
+    }
+}
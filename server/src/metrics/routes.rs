@@ -0,0 +1,15 @@
+use actix_web::{get, web::Data, HttpResponse};
+use prometheus::TEXT_FORMAT;
+
+use super::registry::Metrics;
+
+#[get("/metrics")]
+pub async fn get_metrics(metrics: Data<Metrics>) -> HttpResponse {
+    match metrics.render() {
+        Ok(body) => HttpResponse::Ok().content_type(TEXT_FORMAT).body(body),
+        Err(err) => {
+            log::error!("Couldn't render metrics: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::storage::repository::Profile;
+
+/// Rating of a player who hasn't played yet.
+pub const INITIAL_RATING: i32 = 1200;
+
+/// The maximum rating change in one game.
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Clone, Copy)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Outcome {
+    fn score(&self) -> f64 {
+        match self {
+            Outcome::Win => 1.0,
+            Outcome::Loss => 0.0,
+            Outcome::Draw => 0.5,
+        }
+    }
+}
+
+/// Elo rating change of a player after a game against an opponent.
+pub fn rating_delta(rating: i32, opponent_rating: i32, outcome: Outcome) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((opponent_rating - rating) as f64 / 400.0));
+
+    (K_FACTOR * (outcome.score() - expected)).round() as i32
+}
+
+/// Updates the profile with the game result and returns the rating change.
+pub fn apply_game(profile: &mut Profile, opponent_rating: i32, outcome: Outcome) -> i32 {
+    let delta = rating_delta(profile.rating, opponent_rating, outcome);
+
+    profile.rating += delta;
+    profile.games += 1;
+    match outcome {
+        Outcome::Win => profile.wins += 1,
+        Outcome::Loss => profile.losses += 1,
+        Outcome::Draw => profile.draws += 1,
+    }
+
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rating_delta_of_equal_ratings() {
+        for (outcome, delta) in [(Outcome::Win, 16), (Outcome::Loss, -16), (Outcome::Draw, 0)] {
+            assert_eq!(rating_delta(1200, 1200, outcome), delta);
+        }
+    }
+
+    #[test]
+    fn rating_delta_of_different_ratings() {
+        let cases = [
+            (1400, 1200, Outcome::Win, 8),
+            (1400, 1200, Outcome::Loss, -24),
+            (1400, 1200, Outcome::Draw, -8),
+            (1200, 1400, Outcome::Win, 24),
+            (1200, 1400, Outcome::Loss, -8),
+            (1200, 1400, Outcome::Draw, 8),
+            (2000, 1200, Outcome::Win, 0),
+            (1200, 2000, Outcome::Loss, 0),
+        ];
+
+        for (rating, opponent_rating, outcome, delta) in cases {
+            assert_eq!(
+                rating_delta(rating, opponent_rating, outcome),
+                delta,
+                "{} against {}",
+                rating,
+                opponent_rating
+            );
+        }
+    }
+
+    #[test]
+    fn apply_game_counts_the_outcome() {
+        let cases = [
+            (Outcome::Win, 1216, (1, 0, 0)),
+            (Outcome::Loss, 1184, (0, 1, 0)),
+            (Outcome::Draw, 1200, (0, 0, 1)),
+        ];
+
+        for (outcome, rating, counts) in cases {
+            let mut profile = Profile::new(1);
+            let delta = apply_game(&mut profile, INITIAL_RATING, outcome);
+
+            assert_eq!(delta, rating - INITIAL_RATING);
+            assert_eq!(profile.rating, rating);
+            assert_eq!(profile.games, 1);
+            assert_eq!((profile.wins, profile.losses, profile.draws), counts);
+        }
+    }
+}
@@ -1,18 +1,128 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
 
-use actix::{Actor, Addr, Context, Handler};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler, SpawnHandle};
 use derive_more::Display;
+use rand::seq::SliceRandom;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{server::actor::Server, types::UserId};
+use crate::{
+    config::settings::seconds,
+    metrics::registry::Metrics,
+    server::{
+        actor::Server,
+        messages::{GameOver, RoundStarted, RoundTimedOut},
+    },
+    storage::repository::{GameRecord, MoveRecord, RoundRecord},
+    types::{ErrorCode, UserId},
+};
 
 use super::{
     error::RoomError,
-    messages::{GameFinishedResult, MakeAction, MakeActionResult, RoundFinishedResult},
+    messages::{
+        FinishReason, GameFinishedResult, GetSnapshot, MakeAction, MakeActionResult,
+        PlayerDisconnected, PlayerReconnected, RoomSnapshot, RoundFinishedResult, RoundSnapshot,
+    },
+    ruleset::{Ruleset, RulesetKind},
 };
 
-const WINS_REQURED: u8 = 2;
+const MAX_WINS_REQUIRED: u8 = 10;
+
+const MAX_ROUNDS: u8 = 30;
+
+/// Games without `max_rounds` are decided by the wins after this many rounds,
+/// so two players drawing on purpose can't keep a room forever.
+const ROUND_LIMIT: u8 = 100;
+
+/// Match format chosen by the players.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct RoomConfig {
+    /// Round wins needed to win the game, 2 is best-of-3
+    pub wins_required: u8,
+    /// The game ends after this many rounds, the player with more wins is the winner
+    pub max_rounds: Option<u8>,
+    /// Whether drawn rounds are counted toward `max_rounds`
+    pub count_draws: bool,
+    pub ruleset: RulesetKind,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            wins_required: 2,
+            max_rounds: None,
+            count_draws: true,
+            ruleset: RulesetKind::Classic,
+        }
+    }
+}
+
+impl RoomConfig {
+    pub fn validate(&self) -> Result<(), RoomError> {
+        if self.wins_required == 0 || self.wins_required > MAX_WINS_REQUIRED {
+            return Err(RoomError::new(
+                ErrorCode::InvalidConfig,
+                format!("Wins required must be from 1 to {}", MAX_WINS_REQUIRED),
+            ));
+        }
+
+        if let Some(max_rounds) = self.max_rounds {
+            if max_rounds < self.wins_required || max_rounds > MAX_ROUNDS {
+                return Err(RoomError::new(
+                    ErrorCode::InvalidConfig,
+                    format!(
+                        "Max rounds must be from {} to {}",
+                        self.wins_required, MAX_ROUNDS
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings shared by every room of the server, the `[room]` section of the config.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomSettings {
+    /// How long a disconnected user has to come back before forfeiting the game
+    #[serde(rename = "disconnect_grace_secs", deserialize_with = "seconds")]
+    pub disconnect_grace: Duration,
+    /// How long users have to make their actions in a round
+    #[serde(rename = "round_timeout_secs", deserialize_with = "seconds")]
+    pub round_timeout: Duration,
+    pub timeout_policy: TimeoutPolicy,
+    /// Match format of games started without a config from the client
+    pub default_config: RoomConfig,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            disconnect_grace: Duration::from_secs(30),
+            round_timeout: Duration::from_secs(30),
+            timeout_policy: TimeoutPolicy::default(),
+            default_config: RoomConfig::default(),
+        }
+    }
+}
+
+/// What happens to a user who hasn't made an action before the round deadline.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum TimeoutPolicy {
+    /// The user loses the round. If nobody has made an action the game is abandoned.
+    #[default]
+    LoseRound,
+    /// A random action is made for the user.
+    RandomAction,
+}
 
 pub struct Room {
     id: Uuid,
@@ -20,12 +130,21 @@ pub struct Room {
     users: [UserId; 2],
     rounds: Vec<Round>,
     rounds_count: u8,
+    config: RoomConfig,
+    settings: RoomSettings,
+    forfeit_timers: HashMap<UserId, SpawnHandle>,
+    round_timer: Option<SpawnHandle>,
+    round_deadline: SystemTime,
+    started_at: SystemTime,
+    metrics: Metrics,
 }
 
 struct Round {
     status: RoundStatus,
     actions: Vec<UserAction>,
     winner: Option<UserId>,
+    started_at: SystemTime,
+    finished_at: Option<SystemTime>,
 }
 
 impl Round {
@@ -34,6 +153,8 @@ impl Round {
             status: RoundStatus::InProgress,
             actions: vec![],
             winner: None,
+            started_at: SystemTime::now(),
+            finished_at: None,
         }
     }
 
@@ -41,18 +162,30 @@ impl Round {
         self.actions.push(action);
     }
 
-    fn decide_winner(&mut self) -> Option<UserId> {
+    fn has_action(&self, user_id: UserId) -> bool {
+        self.actions
+            .iter()
+            .any(|user_action| user_action.user_id == user_id)
+    }
+
+    fn decide_winner(&mut self, ruleset: &dyn Ruleset) -> Option<UserId> {
+        // The only user who made an action wins against the absent one
+        if self.actions.len() < 2 {
+            self.winner = self.actions.first().map(|user_action| user_action.user_id);
+            return self.winner;
+        }
+
         let first_user = self.actions.first()?;
         let second_user = self.actions.last()?;
 
         let winner;
 
-        if (first_user.action as u8 + 1u8) % 3 == second_user.action as u8 {
+        if ruleset.beats(second_user.action, first_user.action) {
             winner = Some(second_user.user_id);
-        } else if first_user.action as u8 == second_user.action as u8 {
-            winner = None;
-        } else {
+        } else if ruleset.beats(first_user.action, second_user.action) {
             winner = Some(first_user.user_id);
+        } else {
+            winner = None;
         }
 
         self.winner = winner;
@@ -61,6 +194,7 @@ impl Round {
 
     fn finish(&mut self) {
         self.status = RoundStatus::Completed;
+        self.finished_at = Some(SystemTime::now());
     }
 }
 
@@ -68,6 +202,7 @@ impl Round {
 pub struct UserAction {
     pub user_id: UserId,
     pub action: Action,
+    pub made_at: SystemTime,
 }
 
 enum RoundStatus {
@@ -75,36 +210,234 @@ enum RoundStatus {
     Completed,
 }
 
-#[derive(Serialize, Deserialize, Debug, Display, Clone, Copy)]
-#[repr(u8)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Display, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     Rock,
     Paper,
     Scissors,
+    Lizard,
+    Spock,
 }
 
 impl Actor for Room {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.metrics.rooms.inc();
+        self.metrics.games_started.inc();
+        self.start_round_timer(ctx);
+
+        // The next rounds are announced by the server after the result of the previous one
+        self.server.do_send(RoundStarted {
+            room_id: self.id,
+            users: self.users,
+            round: 1,
+            deadline: self.round_deadline,
+        });
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.metrics.rooms.dec();
+    }
 }
 
 impl Room {
-    pub fn new(id: Uuid, server: Addr<Server>, first_user: UserId, second_user: UserId) -> Self {
+    pub fn new(
+        id: Uuid,
+        server: Addr<Server>,
+        first_user: UserId,
+        second_user: UserId,
+        config: RoomConfig,
+        settings: RoomSettings,
+        metrics: Metrics,
+    ) -> Self {
         Self {
             id,
             server,
             users: [first_user, second_user],
             rounds_count: 0,
             rounds: vec![Round::default()],
+            config,
+            settings,
+            forfeit_timers: HashMap::new(),
+            round_timer: None,
+            round_deadline: SystemTime::now() + settings.round_timeout,
+            started_at: SystemTime::now(),
+            metrics,
         }
     }
 
-    fn start_new_round(&mut self) {
-        self.rounds.push(Round::default());
+    fn start_round_timer(&mut self, ctx: &mut Context<Self>) {
+        let handle = ctx.run_later(self.settings.round_timeout, |room, ctx| {
+            room.round_timer = None;
+            room.timeout_round(ctx);
+        });
+        self.round_timer = Some(handle);
+        self.round_deadline = SystemTime::now() + self.settings.round_timeout;
     }
 
-    fn is_game_over(&self) -> (bool, Option<UserId>) {
-        let winner = self
+    fn timeout_round(&mut self, ctx: &mut Context<Self>) {
+        let Some(round) = self.rounds.last_mut() else {
+            return;
+        };
+
+        log::info!(
+            "Round {} timed out in room {}",
+            self.rounds_count.saturating_add(1),
+            self.id
+        );
+
+        // Players who stopped playing would otherwise draw every round until the server is restarted
+        if round.actions.is_empty() {
+            log::info!("Room {} is abandoned", self.id);
+            let result = GameFinishedResult {
+                winner: None,
+                actions: vec![],
+                users: self.users,
+                reason: FinishReason::Abandoned,
+            };
+            self.finish_game(result, ctx);
+            return;
+        }
+
+        if let TimeoutPolicy::RandomAction = self.settings.timeout_policy {
+            let ruleset = self.config.ruleset.ruleset();
+            for user_id in self.users {
+                if !round.has_action(user_id) {
+                    round.add_action(UserAction {
+                        user_id,
+                        action: *ruleset.actions().choose(&mut rand::thread_rng()).unwrap(),
+                        made_at: SystemTime::now(),
+                    });
+                }
+            }
+        }
+
+        let result = self.complete_round(ctx);
+        self.server.do_send(RoundTimedOut {
+            room_id: self.id,
+            result,
+        });
+    }
+
+    /// Decides the winner of the current round and starts the next one or finishes the game.
+    fn complete_round(&mut self, ctx: &mut Context<Self>) -> MakeActionResult {
+        if let Some(handle) = self.round_timer.take() {
+            ctx.cancel_future(handle);
+        }
+
+        let round = self.rounds.last_mut().unwrap();
+        let actions = round.actions.clone();
+        let winner = round.decide_winner(self.config.ruleset.ruleset());
+        self.rounds_count = self.rounds_count.saturating_add(1);
+        round.finish();
+        if let Some(Ok(duration)) = round
+            .finished_at
+            .map(|finished_at| finished_at.duration_since(round.started_at))
+        {
+            self.metrics.round_duration.observe(duration.as_secs_f64());
+        }
+
+        let (is_finished, game_winner) = self.is_game_over();
+
+        if is_finished {
+            let result = GameFinishedResult {
+                actions,
+                winner: game_winner,
+                users: self.users,
+                reason: FinishReason::Completed,
+            };
+            self.finish_game(result.clone(), ctx);
+
+            return MakeActionResult::GameFinished(result);
+        }
+
+        self.start_new_round(ctx);
+
+        MakeActionResult::RoundFinished(RoundFinishedResult {
+            winner,
+            actions,
+            next_round_cound: self.rounds_count,
+            next_round_deadline: self.round_deadline,
+            users: self.users,
+        })
+    }
+
+    /// Reports the result to the server and stops the room.
+    fn finish_game(&mut self, result: GameFinishedResult, ctx: &mut Context<Self>) {
+        self.metrics.games_finished.inc();
+        if result.reason == FinishReason::Forfeit {
+            self.metrics.games_forfeited.inc();
+        }
+
+        self.server.do_send(GameOver {
+            room_id: self.id,
+            record: self.record(&result),
+            result,
+        });
+        ctx.stop();
+    }
+
+    fn record(&self, result: &GameFinishedResult) -> GameRecord {
+        let rounds = self
             .rounds
+            .iter()
+            .filter(|round| matches!(round.status, RoundStatus::Completed))
+            .enumerate()
+            .map(|(index, round)| RoundRecord {
+                number: u8::try_from(index + 1).unwrap_or(u8::MAX),
+                winner: round.winner,
+                started_at: round.started_at,
+                finished_at: round.finished_at.unwrap_or(round.started_at),
+                moves: round
+                    .actions
+                    .iter()
+                    .map(|user_action| MoveRecord {
+                        user_id: user_action.user_id,
+                        action: user_action.action,
+                        made_at: user_action.made_at,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        GameRecord {
+            id: self.id,
+            users: self.users,
+            winner: result.winner,
+            reason: result.reason,
+            config: self.config,
+            started_at: self.started_at,
+            finished_at: SystemTime::now(),
+            rounds,
+        }
+    }
+
+    fn forfeit(&mut self, user_id: UserId, ctx: &mut Context<Self>) {
+        log::info!("User {} forfeits the game in room {}", user_id, self.id);
+
+        let actions = self
+            .rounds
+            .last()
+            .map(|round| round.actions.clone())
+            .unwrap_or_default();
+
+        let result = GameFinishedResult {
+            winner: self.users.iter().find(|u| **u != user_id).cloned(),
+            actions,
+            users: self.users,
+            reason: FinishReason::Forfeit,
+        };
+        self.finish_game(result, ctx);
+    }
+
+    fn start_new_round(&mut self, ctx: &mut Context<Self>) {
+        self.rounds.push(Round::default());
+        self.start_round_timer(ctx);
+    }
+
+    fn wins(&self) -> HashMap<UserId, u8> {
+        self.rounds
             .iter()
             .fold(HashMap::<UserId, u8>::new(), |mut map, round| {
                 if let Some(user_id) = round.winner {
@@ -112,29 +445,57 @@ impl Room {
                 }
                 map
             })
+    }
+
+    fn is_game_over(&self) -> (bool, Option<UserId>) {
+        let wins = self.wins();
+        let winner = wins
             .iter()
-            .filter(|(_user_id, wins)| **wins >= WINS_REQURED)
+            .filter(|(_user_id, wins)| **wins >= self.config.wins_required)
             .map(|(user_id, _)| *user_id)
             .collect::<Vec<UserId>>();
 
-        if winner.len() == 0 {
-            return (false, None);
-        } else if winner.len() > 1 {
+        if winner.len() > 1 {
             // Drow
             return (true, None);
+        } else if !winner.is_empty() {
+            return (true, winner.first().cloned());
+        }
+
+        let played = if self.config.count_draws {
+            self.rounds_count
+        } else {
+            wins.values().sum()
+        };
+        let is_max_rounds = self
+            .config
+            .max_rounds
+            .is_some_and(|max_rounds| played >= max_rounds);
+
+        if is_max_rounds || self.rounds_count >= ROUND_LIMIT {
+            let [first_user, second_user] = self.users;
+            let first_wins = wins.get(&first_user).cloned().unwrap_or_default();
+            let second_wins = wins.get(&second_user).cloned().unwrap_or_default();
+
+            return match first_wins.cmp(&second_wins) {
+                Ordering::Greater => (true, Some(first_user)),
+                Ordering::Less => (true, Some(second_user)),
+                Ordering::Equal => (true, None),
+            };
         }
 
-        (true, winner.first().cloned())
+        (false, None)
     }
 }
 
 impl Handler<MakeAction> for Room {
     type Result = Result<MakeActionResult, RoomError>;
 
-    fn handle(&mut self, msg: MakeAction, _ctx: &mut Self::Context) -> Self::Result {
-        let round = self.rounds.last_mut().ok_or(RoomError {
-            message: "Room initialization error. Try again".to_owned(),
-        })?;
+    fn handle(&mut self, msg: MakeAction, ctx: &mut Self::Context) -> Self::Result {
+        let round = self.rounds.last_mut().ok_or(RoomError::new(
+            ErrorCode::Internal,
+            "Room initialization error. Try again",
+        ))?;
 
         if !self.users.contains(&msg.user_id) {
             log::warn!(
@@ -142,54 +503,111 @@ impl Handler<MakeAction> for Room {
                 msg.user_id,
                 self.id,
             );
-            return Err(RoomError {
-                message: "You are not a part of this room".to_owned(),
-            });
+            return Err(RoomError::new(
+                ErrorCode::NotInRoom,
+                "You are not a part of this room",
+            ));
         }
 
-        if round
-            .actions
-            .iter()
-            .any(|user_action| user_action.user_id == msg.user_id)
-        {
+        if !self.config.ruleset.ruleset().is_legal(msg.action) {
+            return Err(RoomError::new(
+                ErrorCode::ActionNotAllowed,
+                format!("{} is not allowed in this room", msg.action),
+            ));
+        }
+
+        if round.has_action(msg.user_id) {
             log::warn!("User {} is trying to change its action", msg.user_id);
-            return Err(RoomError {
-                message: "You cannot change your action".to_owned(),
-            });
+            return Err(RoomError::new(
+                ErrorCode::ActionAlreadySubmitted,
+                "You cannot change your action",
+            ));
         }
 
         round.add_action(UserAction {
             user_id: msg.user_id,
             action: msg.action,
+            made_at: SystemTime::now(),
         });
 
-        let actions = round.actions.clone();
-
         if round.actions.len() == 2 {
-            let winner = round.decide_winner();
-            self.rounds_count += 1;
-            round.finish();
-
-            let (is_finished, game_winner) = self.is_game_over();
-
-            if is_finished {
-                return Ok(MakeActionResult::GameFinished(GameFinishedResult {
-                    actions,
-                    winner: game_winner,
-                    users: self.users,
-                }));
-            }
+            return Ok(self.complete_round(ctx));
+        }
+
+        Ok(MakeActionResult::Accepted)
+    }
+}
 
-            self.start_new_round();
+impl Handler<PlayerDisconnected> for Room {
+    type Result = ();
 
-            return Ok(MakeActionResult::RoundFinished(RoundFinishedResult {
-                winner,
-                actions,
-                next_round_cound: self.rounds_count,
-                users: self.users,
-            }));
+    fn handle(&mut self, msg: PlayerDisconnected, ctx: &mut Self::Context) -> Self::Result {
+        if !self.users.contains(&msg.user_id) || self.forfeit_timers.contains_key(&msg.user_id) {
+            return;
         }
 
-        Ok(MakeActionResult::Accepted)
+        let user_id = msg.user_id;
+        let handle = ctx.run_later(self.settings.disconnect_grace, move |room, ctx| {
+            room.forfeit_timers.remove(&user_id);
+            room.forfeit(user_id, ctx);
+        });
+        self.forfeit_timers.insert(user_id, handle);
+    }
+}
+
+impl Handler<PlayerReconnected> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: PlayerReconnected, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(handle) = self.forfeit_timers.remove(&msg.user_id) {
+            ctx.cancel_future(handle);
+        }
+    }
+}
+
+impl Handler<GetSnapshot> for Room {
+    type Result = Result<RoomSnapshot, RoomError>;
+
+    fn handle(&mut self, msg: GetSnapshot, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.users.contains(&msg.user_id) {
+            return Err(RoomError::new(
+                ErrorCode::NotInRoom,
+                "You are not a part of this room",
+            ));
+        }
+
+        let opponent = *self.users.iter().find(|u| **u != msg.user_id).unwrap();
+
+        let rounds = self
+            .rounds
+            .iter()
+            .filter(|round| matches!(round.status, RoundStatus::Completed))
+            .map(|round| RoundSnapshot {
+                winner: round.winner,
+                actions: round.actions.clone(),
+            })
+            .collect();
+
+        let my_action = self.rounds.last().and_then(|round| {
+            round
+                .actions
+                .iter()
+                .find(|user_action| user_action.user_id == msg.user_id)
+                .map(|user_action| user_action.action)
+        });
+
+        let wins = self.wins();
+
+        Ok(RoomSnapshot {
+            room_id: self.id,
+            opponent,
+            rounds,
+            wins: wins.get(&msg.user_id).cloned().unwrap_or_default(),
+            opponent_wins: wins.get(&opponent).cloned().unwrap_or_default(),
+            next_round_count: self.rounds_count,
+            my_action,
+            deadline: self.round_deadline,
+            config: self.config,
+        })
     }
 }
@@ -1,6 +1,19 @@
 use derive_more::{Display, Error};
 
+use crate::types::ErrorCode;
+
 #[derive(Debug, Display, Error)]
+#[display(fmt = "{}", message)]
 pub struct RoomError {
+    pub code: ErrorCode,
     pub message: String,
 }
+
+impl RoomError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use std::time::SystemTime;
+
 use actix::Message;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-use crate::types::UserId;
+use crate::types::{RoomId, UserId};
 
 use super::{
-    actor::{Action, UserAction},
+    actor::{Action, RoomConfig, UserAction},
     error::RoomError,
 };
 
@@ -26,12 +30,59 @@ pub struct RoundFinishedResult {
     pub winner: Option<UserId>,
     pub actions: Vec<UserAction>,
     pub next_round_cound: u8,
+    pub next_round_deadline: SystemTime,
     pub users: [UserId; 2],
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PlayerDisconnected {
+    pub user_id: UserId,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PlayerReconnected {
+    pub user_id: UserId,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<RoomSnapshot, RoomError>")]
+pub struct GetSnapshot {
+    pub user_id: UserId,
+}
+
+/// State of a room as seen by one of its users.
+pub struct RoomSnapshot {
+    pub room_id: RoomId,
+    pub opponent: UserId,
+    pub rounds: Vec<RoundSnapshot>,
+    pub wins: u8,
+    pub opponent_wins: u8,
+    pub next_round_count: u8,
+    /// The action already submitted by the user in the current round
+    pub my_action: Option<Action>,
+    pub deadline: SystemTime,
+    pub config: RoomConfig,
+}
+
+pub struct RoundSnapshot {
+    pub winner: Option<UserId>,
+    pub actions: Vec<UserAction>,
+}
+
 #[derive(Clone)]
 pub struct GameFinishedResult {
     pub winner: Option<UserId>,
     pub actions: Vec<UserAction>,
     pub users: [UserId; 2],
+    pub reason: FinishReason,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+pub enum FinishReason {
+    Completed,
+    Forfeit,
+    /// Neither player made an action before the round deadline
+    Abandoned,
 }
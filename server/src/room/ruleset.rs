@@ -0,0 +1,203 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::actor::Action;
+
+/// Rules of a game: which actions can be made and which action beats which.
+pub trait Ruleset: Sync {
+    fn actions(&self) -> &'static [Action];
+
+    fn beats(&self, action: Action, other: Action) -> bool;
+
+    fn is_legal(&self, action: Action) -> bool {
+        self.actions().contains(&action)
+    }
+}
+
+/// Rock, Paper, Scissors.
+pub struct Classic;
+
+/// Rock, Paper, Scissors, Lizard, Spock.
+pub struct Rpsls;
+
+const CLASSIC_ACTIONS: &[Action] = &[Action::Rock, Action::Paper, Action::Scissors];
+
+const CLASSIC_BEATS: &[(Action, Action)] = &[
+    (Action::Rock, Action::Scissors),
+    (Action::Paper, Action::Rock),
+    (Action::Scissors, Action::Paper),
+];
+
+const RPSLS_ACTIONS: &[Action] = &[
+    Action::Rock,
+    Action::Paper,
+    Action::Scissors,
+    Action::Lizard,
+    Action::Spock,
+];
+
+const RPSLS_BEATS: &[(Action, Action)] = &[
+    (Action::Scissors, Action::Paper),
+    (Action::Paper, Action::Rock),
+    (Action::Rock, Action::Lizard),
+    (Action::Lizard, Action::Spock),
+    (Action::Spock, Action::Scissors),
+    (Action::Scissors, Action::Lizard),
+    (Action::Lizard, Action::Paper),
+    (Action::Paper, Action::Spock),
+    (Action::Spock, Action::Rock),
+    (Action::Rock, Action::Scissors),
+];
+
+impl Ruleset for Classic {
+    fn actions(&self) -> &'static [Action] {
+        CLASSIC_ACTIONS
+    }
+
+    fn beats(&self, action: Action, other: Action) -> bool {
+        CLASSIC_BEATS.contains(&(action, other))
+    }
+}
+
+impl Ruleset for Rpsls {
+    fn actions(&self) -> &'static [Action] {
+        RPSLS_ACTIONS
+    }
+
+    fn beats(&self, action: Action, other: Action) -> bool {
+        RPSLS_BEATS.contains(&(action, other))
+    }
+}
+
+/// Built-in rulesets a room can be played with.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RulesetKind {
+    #[default]
+    Classic,
+    Rpsls,
+}
+
+impl RulesetKind {
+    pub fn ruleset(&self) -> &'static dyn Ruleset {
+        match self {
+            RulesetKind::Classic => &Classic,
+            RulesetKind::Rpsls => &Rpsls,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Outcome {
+        Win,
+        Lose,
+        Draw,
+    }
+
+    use Outcome::{Draw as D, Lose as L, Win as W};
+
+    /// Outcome for the row action against the column action, in the order of `actions()`.
+    const CLASSIC_OUTCOMES: [[Outcome; 3]; 3] = [
+        // Rock, Paper, Scissors
+        [D, L, W], // Rock
+        [W, D, L], // Paper
+        [L, W, D], // Scissors
+    ];
+
+    const RPSLS_OUTCOMES: [[Outcome; 5]; 5] = [
+        // Rock, Paper, Scissors, Lizard, Spock
+        [D, L, W, W, L], // Rock
+        [W, D, L, L, W], // Paper
+        [L, W, D, W, L], // Scissors
+        [L, W, L, D, W], // Lizard
+        [W, L, W, L, D], // Spock
+    ];
+
+    fn outcome(ruleset: &dyn Ruleset, action: Action, other: Action) -> Outcome {
+        match (ruleset.beats(action, other), ruleset.beats(other, action)) {
+            (true, false) => Outcome::Win,
+            (false, true) => Outcome::Lose,
+            (false, false) => Outcome::Draw,
+            (true, true) => panic!("{} and {} beat each other", action, other),
+        }
+    }
+
+    fn assert_outcomes<const N: usize>(ruleset: &dyn Ruleset, expected: &[[Outcome; N]; N]) {
+        let actions = ruleset.actions();
+        assert_eq!(actions.len(), N);
+
+        for (row, action) in actions.iter().enumerate() {
+            for (column, other) in actions.iter().enumerate() {
+                assert_eq!(
+                    outcome(ruleset, *action, *other),
+                    expected[row][column],
+                    "{} against {}",
+                    action,
+                    other
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn classic_outcomes() {
+        assert_eq!(
+            Classic.actions(),
+            &[Action::Rock, Action::Paper, Action::Scissors]
+        );
+        assert_outcomes(&Classic, &CLASSIC_OUTCOMES);
+    }
+
+    #[test]
+    fn rpsls_outcomes() {
+        assert_eq!(
+            Rpsls.actions(),
+            &[
+                Action::Rock,
+                Action::Paper,
+                Action::Scissors,
+                Action::Lizard,
+                Action::Spock
+            ]
+        );
+        assert_outcomes(&Rpsls, &RPSLS_OUTCOMES);
+    }
+
+    #[test]
+    fn beats_is_antisymmetric() {
+        for kind in [RulesetKind::Classic, RulesetKind::Rpsls] {
+            let ruleset = kind.ruleset();
+            for action in RPSLS_ACTIONS {
+                assert!(!ruleset.beats(*action, *action), "{} beats itself", action);
+                for other in RPSLS_ACTIONS {
+                    assert!(
+                        !(ruleset.beats(*action, *other) && ruleset.beats(*other, *action)),
+                        "{} and {} beat each other in {:?}",
+                        action,
+                        other,
+                        kind
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn classic_rejects_lizard_and_spock() {
+        assert!(!Classic.is_legal(Action::Lizard));
+        assert!(!Classic.is_legal(Action::Spock));
+        for action in CLASSIC_ACTIONS {
+            assert!(Classic.is_legal(*action));
+        }
+    }
+
+    #[test]
+    fn rpsls_accepts_every_action() {
+        for action in RPSLS_ACTIONS {
+            assert!(Rpsls.is_legal(*action));
+        }
+    }
+}
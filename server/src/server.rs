@@ -1,3 +1,4 @@
 pub mod actor;
 pub mod error;
+pub mod matchmaker;
 pub mod messages;
@@ -1,61 +1,596 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime},
+};
 
 use actix::*;
+use rand::seq::SliceRandom;
 use std::convert::From;
 use uuid::Uuid;
 
 use crate::{
+    config::settings::{MatchmakingConfig, ServerConfig},
+    metrics::registry::Metrics,
+    rating::INITIAL_RATING,
     room::{
-        actor::Room,
-        messages::{MakeAction, MakeActionResult},
+        actor::{Room, RoomConfig, RoomSettings},
+        messages::{
+            GetSnapshot, MakeAction, MakeActionResult, PlayerDisconnected, PlayerReconnected,
+        },
     },
-    types::{RoomId, UserId},
+    storage::{
+        actor::Storage,
+        messages::{LoadLeaderboard, LoadProfile, SaveGame, SaveUser},
+        repository::{LeaderboardQuery, Profile},
+    },
+    types::{ErrorCode, RoomId, UserId},
     websockets::{
         client_messages::{
-            IncomingClientMessage, MatchmakingSuccessPayload, OutgoingClientMessage,
+            to_unix_millis, GameFinishedPayload, IncomingClientMessage, MatchmakingSuccessPayload,
+            OutgoingClientMessage, RatingChangePayload, RematchPayload, RoomSnapshotPayload,
+            RoundStartedPayload, ServerShuttingDownPayload, MAX_LEADERBOARD_LIMIT,
         },
         messages::{Close, SendClientMessage},
         ws::Connection,
     },
 };
+use actix_web_actors::ws::CloseCode;
 
 use super::{
     error::ServerError,
+    matchmaker::{Matchmaker, QueuedPlayer},
     messages::{
-        AttachConnection, MatchmakingStatus, ProcessClientMessage, ProcessClientMessageResult,
-        StartMatchmakingResultPayload,
+        AttachConnection, DetachConnection, GameOver, GetStatus, MatchmakingStatus,
+        PrivateRoomCreatedResultPayload, ProcessClientMessage, ProcessClientMessageResult,
+        RoundStarted, RoundTimedOut, ServerStatus, Shutdown, StartMatchmakingResultPayload,
     },
 };
 
+const INVITE_CODE_LENGTH: usize = 6;
+
+/// Letters and digits that can't be confused with each other when typed by hand.
+/// The code is also a valid Telegram `startapp` parameter.
+const INVITE_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// How often running games are checked while the server is shutting down.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct Server {
-    connections: HashMap<UserId, Addr<Connection>>,
-    matchmaking_queue: VecDeque<UserId>,
+    connections: HashMap<UserId, UserConnection>,
+    matchmaker: Matchmaker,
+    /// Ratings of connected users
+    ratings: HashMap<UserId, i32>,
     rooms: HashMap<RoomId, Addr<Room>>,
+    user_rooms: HashMap<UserId, RoomId>,
+    private_rooms: HashMap<String, PrivateRoom>,
+    finished_rooms: HashMap<RoomId, FinishedRoom>,
+    settings: ServerConfig,
+    matchmaking_interval: Duration,
+    room_settings: RoomSettings,
+    storage: Addr<Storage>,
+    metrics: Metrics,
+    /// Games still being saved, their players wait for `GameFinished`
+    saving_games: usize,
+    /// Set when the server is shutting down, running games must finish before it
+    shutdown_deadline: Option<Instant>,
+}
+
+/// Websocket connection of a user, kept as recipients of the messages the server sends to it.
+struct UserConnection {
+    messages: Recipient<SendClientMessage>,
+    close: Recipient<Close>,
+}
+
+impl From<Addr<Connection>> for UserConnection {
+    fn from(connection: Addr<Connection>) -> Self {
+        Self {
+            messages: connection.clone().recipient(),
+            close: connection.recipient(),
+        }
+    }
+}
+
+/// A finished room which players can still ask for a rematch.
+struct FinishedRoom {
+    users: [UserId; 2],
+    config: RoomConfig,
+    rematch_requests: Vec<UserId>,
+}
+
+/// A room waiting for the invited friend.
+struct PrivateRoom {
+    owner: UserId,
+    config: RoomConfig,
+    created_at: Instant,
+    room: Option<RoomId>,
+}
+
+impl PrivateRoom {
+    fn is_expired(&self, ttl: Duration) -> bool {
+        self.created_at.elapsed() > ttl
+    }
 }
 
 impl Server {
-    pub fn new() -> Self {
+    pub fn new(
+        settings: ServerConfig,
+        matchmaking: MatchmakingConfig,
+        room_settings: RoomSettings,
+        storage: Addr<Storage>,
+        metrics: Metrics,
+    ) -> Self {
         Self {
             connections: HashMap::new(),
-            matchmaking_queue: VecDeque::new(),
+            matchmaker: Matchmaker::new(&matchmaking),
+            ratings: HashMap::new(),
             rooms: HashMap::new(),
+            user_rooms: HashMap::new(),
+            private_rooms: HashMap::new(),
+            finished_rooms: HashMap::new(),
+            settings,
+            matchmaking_interval: matchmaking.interval,
+            room_settings,
+            storage,
+            metrics,
+            saving_games: 0,
+            shutdown_deadline: None,
+        }
+    }
+
+    fn create_room(
+        &mut self,
+        first_user: UserId,
+        second_user: UserId,
+        config: RoomConfig,
+        ctx: &mut Context<Self>,
+    ) -> RoomId {
+        let room_id = Uuid::new_v4();
+        let room = Room::new(
+            room_id,
+            ctx.address(),
+            first_user,
+            second_user,
+            config,
+            self.room_settings,
+            self.metrics.clone(),
+        )
+        .start();
+        self.rooms.insert(room_id, room);
+        for user_id in [first_user, second_user] {
+            // Otherwise the next matchmaking tick could put the player in a second room
+            self.remove_from_queue(user_id);
+            self.user_rooms.insert(user_id, room_id);
+        }
+
+        room_id
+    }
+
+    fn generate_invite_code(&self) -> String {
+        let mut rng = rand::thread_rng();
+
+        loop {
+            let code = (0..INVITE_CODE_LENGTH)
+                .map(|_| *INVITE_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+                .collect::<String>();
+
+            if !self.private_rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    fn remove_pending_private_rooms(&mut self, user_id: UserId) {
+        self.private_rooms
+            .retain(|_, private_room| private_room.owner != user_id || private_room.room.is_some());
+    }
+
+    fn send_to_user(&self, user_id: UserId, message: OutgoingClientMessage) {
+        match self.connections.get(&user_id) {
+            Some(connection) => connection.messages.do_send(SendClientMessage { message }),
+            None => log::warn!("User {} has no connection, message is dropped", user_id),
         }
     }
+
+    /// Announces the next round. It must follow the result of the previous round,
+    /// otherwise clients would show the new countdown before the outcome.
+    fn send_round_started(
+        &self,
+        room_id: RoomId,
+        users: [UserId; 2],
+        round: u8,
+        deadline: SystemTime,
+    ) {
+        for user_id in users {
+            self.send_to_user(
+                user_id,
+                OutgoingClientMessage::RoundStarted(RoundStartedPayload {
+                    room: room_id,
+                    round,
+                    deadline: to_unix_millis(deadline),
+                }),
+            );
+        }
+    }
+
+    fn user_room(&self, user_id: UserId) -> Option<&Addr<Room>> {
+        self.user_rooms
+            .get(&user_id)
+            .and_then(|room_id| self.rooms.get(room_id))
+    }
+
+    fn drop_disconnected_from_queue(&mut self) {
+        let connections = &self.connections;
+        self.matchmaker.retain(|queued| {
+            let is_connected = connections.contains_key(&queued.user_id);
+            if !is_connected {
+                log::warn!(
+                    "Dropping user {} without connection from the queue",
+                    queued.user_id
+                );
+            }
+            is_connected
+        });
+    }
+
+    /// Takes the queued user and an opponent with a live connection out of the queue.
+    fn take_opponent(&mut self, user_id: UserId) -> Option<UserId> {
+        self.drop_disconnected_from_queue();
+        let (player, opponent) = self.matchmaker.take_match(user_id)?;
+        self.observe_wait(&[player, opponent]);
+
+        Some(opponent.user_id)
+    }
+
+    /// Starts games for queued players whose rating bands have become wide enough.
+    fn match_queued_players(&mut self, ctx: &mut Context<Self>) {
+        self.drop_disconnected_from_queue();
+
+        for (first, second) in self.matchmaker.take_matches() {
+            self.observe_wait(&[first, second]);
+            let room_id = self.create_room(first.user_id, second.user_id, first.config, ctx);
+
+            for (user_id, opponent) in [
+                (first.user_id, second.user_id),
+                (second.user_id, first.user_id),
+            ] {
+                self.send_to_user(
+                    user_id,
+                    OutgoingClientMessage::MatchmakingSuccess(MatchmakingSuccessPayload {
+                        room: room_id,
+                        opponent,
+                        config: first.config,
+                    }),
+                );
+            }
+        }
+    }
+
+    fn observe_wait(&self, players: &[QueuedPlayer]) {
+        for player in players {
+            self.metrics
+                .matchmaking_wait
+                .observe(player.joined_at.elapsed().as_secs_f64());
+        }
+    }
+
+    fn remove_from_queue(&mut self, user_id: UserId) {
+        self.matchmaker.remove(user_id);
+    }
+
+    fn shutting_down_message(&self) -> Option<OutgoingClientMessage> {
+        let deadline = self.shutdown_deadline?;
+
+        Some(OutgoingClientMessage::ServerShuttingDown(
+            ServerShuttingDownPayload {
+                grace_seconds: deadline.saturating_duration_since(Instant::now()).as_secs(),
+            },
+        ))
+    }
+
+    /// Resolves once every running game is finished and saved, or the grace period is over.
+    fn wait_for_games(&self) -> ResponseActFuture<Self, ()> {
+        Box::pin(
+            fut::wrap_future::<_, Self>(actix::clock::sleep(SHUTDOWN_CHECK_INTERVAL)).then(
+                |_, server, _ctx| -> ResponseActFuture<Self, ()> {
+                    if server.rooms.is_empty() && server.saving_games == 0 {
+                        return Box::pin(fut::ready(()));
+                    }
+
+                    if server
+                        .shutdown_deadline
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        log::warn!(
+                            "{} games are still running after the grace period and will be lost",
+                            server.rooms.len()
+                        );
+                        return Box::pin(fut::ready(()));
+                    }
+
+                    server.wait_for_games()
+                },
+            ),
+        )
+    }
 }
 
 impl Actor for Server {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.settings.invite_code_ttl, |server, _ctx| {
+            let ttl = server.settings.invite_code_ttl;
+            server
+                .private_rooms
+                .retain(|_, private_room| !private_room.is_expired(ttl));
+        });
+
+        ctx.run_interval(self.matchmaking_interval, |server, ctx| {
+            server.match_queued_players(ctx);
+            server
+                .metrics
+                .queue_length
+                .set(server.matchmaker.len() as i64);
+        });
+    }
 }
 
 impl Handler<AttachConnection> for Server {
     type Result = ();
 
-    fn handle(&mut self, msg: AttachConnection, _ctx: &mut Self::Context) -> Self::Result {
-        if let Some(old_connection) = self.connections.insert(msg.user_id, msg.connection) {
-            old_connection.do_send(Close {
+    fn handle(&mut self, msg: AttachConnection, ctx: &mut Self::Context) -> Self::Result {
+        let connection = msg.connection.clone().recipient::<SendClientMessage>();
+        let user_id = msg.user_id;
+
+        self.storage
+            .send(SaveUser {
+                user_id,
+                display_name: msg.display_name,
+            })
+            .into_actor(self)
+            .map(move |res, _server, _ctx| match res {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => log::error!("Couldn't save user {}: {}", user_id, err),
+                Err(err) => log::error!("Couldn't send message to storage: {}", err),
+            })
+            .spawn(ctx);
+
+        // Matchmaking uses the rating, keep it at hand while the user is online
+        self.storage
+            .send(LoadProfile { user_id })
+            .into_actor(self)
+            .map(move |res, server, _ctx| match res {
+                Ok(Ok(profile)) => {
+                    if server.connections.contains_key(&user_id) {
+                        let rating = profile.map_or(INITIAL_RATING, |profile| profile.rating);
+                        server.ratings.insert(user_id, rating);
+                    }
+                }
+                Ok(Err(err)) => log::error!("Couldn't load profile of user {}: {}", user_id, err),
+                Err(err) => log::error!("Couldn't send message to storage: {}", err),
+            })
+            .spawn(ctx);
+
+        if let Some(old_connection) = self
+            .connections
+            .insert(msg.user_id, UserConnection::from(msg.connection))
+        {
+            old_connection.close.do_send(Close {
+                code: CloseCode::Normal,
                 reason: "Only one connection per user".to_owned(),
             });
         }
+
+        if let Some(message) = self.shutting_down_message() {
+            self.send_to_user(msg.user_id, message);
+        }
+
+        if let Some(room) = self.user_room(msg.user_id) {
+            room.do_send(PlayerReconnected {
+                user_id: msg.user_id,
+            });
+
+            // Let the reconnected user continue the game where it stopped
+            room.send(GetSnapshot {
+                user_id: msg.user_id,
+            })
+            .into_actor(self)
+            .map(move |res, _server, _ctx| match res {
+                Ok(Ok(snapshot)) => connection.do_send(SendClientMessage {
+                    message: OutgoingClientMessage::ResumeSession(RoomSnapshotPayload::from(
+                        snapshot,
+                    )),
+                }),
+                Ok(Err(err)) => log::warn!("Couldn't resume session: {}", err),
+                Err(err) => log::error!("Couldn't send message to room: {}", err),
+            })
+            .spawn(ctx);
+        }
+    }
+}
+
+impl Handler<DetachConnection> for Server {
+    type Result = ();
+
+    fn handle(&mut self, msg: DetachConnection, _ctx: &mut Self::Context) -> Self::Result {
+        // The user may have already reconnected, keep the newer connection
+        let connection = msg.connection.recipient::<SendClientMessage>();
+        if self.connections.get(&msg.user_id).map(|c| &c.messages) != Some(&connection) {
+            return;
+        }
+
+        self.connections.remove(&msg.user_id);
+        self.ratings.remove(&msg.user_id);
+        self.remove_from_queue(msg.user_id);
+        self.remove_pending_private_rooms(msg.user_id);
+
+        if let Some(room) = self.user_room(msg.user_id) {
+            room.do_send(PlayerDisconnected {
+                user_id: msg.user_id,
+            });
+        }
+    }
+}
+
+impl Handler<GameOver> for Server {
+    type Result = ();
+
+    fn handle(&mut self, msg: GameOver, ctx: &mut Self::Context) -> Self::Result {
+        self.rooms.remove(&msg.room_id);
+        for user_id in msg.result.users {
+            if self.user_rooms.get(&user_id) == Some(&msg.room_id) {
+                self.user_rooms.remove(&user_id);
+            }
+        }
+
+        let room_id = msg.room_id;
+        let config = msg.record.config;
+        let result = msg.result.clone();
+
+        // Both players learn about the end of the game once their ratings are updated
+        self.saving_games += 1;
+        self.storage
+            .send(SaveGame { game: msg.record })
+            .into_actor(self)
+            .map(move |res, server, _ctx| {
+                server.saving_games -= 1;
+                let changes = match res {
+                    Ok(Ok(changes)) => changes,
+                    Ok(Err(err)) => {
+                        log::error!("Couldn't save game {}: {}", room_id, err);
+                        vec![]
+                    }
+                    Err(err) => {
+                        log::error!("Couldn't send message to storage: {}", err);
+                        vec![]
+                    }
+                };
+
+                for change in &changes {
+                    if let Some(rating) = server.ratings.get_mut(&change.user_id) {
+                        *rating = change.rating;
+                    }
+                }
+
+                for user_id in result.users {
+                    // The player who forfeited has already left
+                    let Some(connection) = server.connections.get(&user_id) else {
+                        continue;
+                    };
+
+                    let mut payload = GameFinishedPayload::from(result.clone());
+                    payload.rating = changes
+                        .iter()
+                        .find(|change| change.user_id == user_id)
+                        .map(RatingChangePayload::from);
+
+                    connection.messages.do_send(SendClientMessage {
+                        message: OutgoingClientMessage::GameFinished(payload),
+                    });
+                }
+            })
+            .spawn(ctx);
+
+        self.finished_rooms.insert(
+            msg.room_id,
+            FinishedRoom {
+                users: msg.result.users,
+                config,
+                rematch_requests: vec![],
+            },
+        );
+
+        ctx.run_later(self.settings.rematch_window, move |server, _ctx| {
+            // Nobody answered the offer in time
+            if let Some(finished_room) = server.finished_rooms.remove(&room_id) {
+                for user_id in finished_room.rematch_requests {
+                    server.send_to_user(
+                        user_id,
+                        OutgoingClientMessage::RematchDeclined(RematchPayload { room: room_id }),
+                    );
+                }
+            }
+        });
+    }
+}
+
+impl Handler<Shutdown> for Server {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        log::info!(
+            "Shutting down, waiting up to {:?} for {} running games",
+            msg.grace,
+            self.rooms.len()
+        );
+        self.shutdown_deadline = Some(Instant::now() + msg.grace);
+
+        for queued in self.matchmaker.clear() {
+            self.send_to_user(queued.user_id, OutgoingClientMessage::MatchmakingCancelled);
+        }
+        self.private_rooms
+            .retain(|_, private_room| private_room.room.is_some());
+
+        for user_id in self.connections.keys() {
+            if let Some(message) = self.shutting_down_message() {
+                self.send_to_user(*user_id, message);
+            }
+        }
+
+        Box::pin(self.wait_for_games().map(|_, server, _ctx| {
+            log::info!("Closing {} connections", server.connections.len());
+            for connection in server.connections.values() {
+                connection.close.do_send(Close {
+                    code: CloseCode::Away,
+                    reason: "Server is shutting down".to_owned(),
+                });
+            }
+        }))
+    }
+}
+
+impl Handler<GetStatus> for Server {
+    type Result = ServerStatus;
+
+    fn handle(&mut self, _msg: GetStatus, _ctx: &mut Self::Context) -> Self::Result {
+        ServerStatus {
+            shutting_down: self.shutdown_deadline.is_some(),
+        }
+    }
+}
+
+impl Handler<RoundStarted> for Server {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoundStarted, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_round_started(msg.room_id, msg.users, msg.round, msg.deadline);
+    }
+}
+
+impl Handler<RoundTimedOut> for Server {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoundTimedOut, _ctx: &mut Self::Context) -> Self::Result {
+        let result = match msg.result {
+            MakeActionResult::RoundFinished(ref result) => result,
+            // Reported by the GameOver handler together with rating changes
+            MakeActionResult::GameFinished(_) | MakeActionResult::Accepted => return,
+        };
+
+        for user_id in result.users {
+            self.send_to_user(
+                user_id,
+                OutgoingClientMessage::from(ProcessClientMessageResult::MakeActionResult(
+                    msg.result.clone(),
+                )),
+            );
+        }
+        self.send_round_started(
+            msg.room_id,
+            result.users,
+            result.next_round_cound.saturating_add(1),
+            result.next_round_deadline,
+        );
     }
 }
 
@@ -63,32 +598,66 @@ impl Handler<ProcessClientMessage> for Server {
     type Result = ResponseActFuture<Self, Result<ProcessClientMessageResult, ServerError>>;
 
     fn handle(&mut self, msg: ProcessClientMessage, ctx: &mut Self::Context) -> Self::Result {
-        match msg.message {
-            IncomingClientMessage::StartMatchmaking => {
-                if self.matchmaking_queue.len() > 0 {
-                    let opponent = self.matchmaking_queue.pop_front().unwrap();
-                    let opponent_connection = self.connections.get(&opponent);
-
-                    if opponent_connection.is_none() {
-                        log::error!("Connection not found!");
-                        return Box::pin(fut::ready(Err(ServerError {
-                            message: "Opponent connection is not initialized".to_owned(),
-                        })));
-                    }
+        let starts_game = matches!(
+            msg.message,
+            IncomingClientMessage::StartMatchmaking(_)
+                | IncomingClientMessage::CreatePrivateRoom(_)
+                | IncomingClientMessage::JoinPrivateRoom(_)
+                | IncomingClientMessage::RequestRematch(_)
+        );
+        if starts_game && self.shutdown_deadline.is_some() {
+            return Box::pin(fut::ready(Err(ServerError::new(
+                ErrorCode::ServerShuttingDown,
+                "Server is shutting down",
+            ))));
+        }
+        if starts_game && self.user_rooms.contains_key(&msg.user_id) {
+            return Box::pin(fut::ready(Err(ServerError::new(
+                ErrorCode::AlreadyInRoom,
+                "You are already in a game",
+            ))));
+        }
 
-                    let room_id = Uuid::new_v4();
-                    let room = Room::new(room_id, ctx.address(), msg.user_id, opponent).start();
-                    self.rooms.insert(room_id, room);
+        match msg.message {
+            IncomingClientMessage::StartMatchmaking(config) => {
+                let config = config.unwrap_or(self.room_settings.default_config);
+                if let Err(err) = config.validate() {
+                    return Box::pin(fut::ready(Err(ServerError::from(err))));
+                }
 
-                    // Send message to the opponent about success matchmaking
-                    opponent_connection.unwrap().do_send(SendClientMessage {
-                        message: OutgoingClientMessage::MatchmakingSuccess(
-                            MatchmakingSuccessPayload {
-                                room: room_id,
-                                opponent: msg.user_id,
+                if self.matchmaker.contains(msg.user_id, config) {
+                    return Box::pin(fut::ready(Ok(
+                        ProcessClientMessageResult::StartMatchmakingResult(
+                            StartMatchmakingResultPayload {
+                                opponent: None,
+                                status: MatchmakingStatus::Searching,
+                                room: None,
+                                config,
                             },
                         ),
-                    });
+                    )));
+                }
+
+                let rating = self
+                    .ratings
+                    .get(&msg.user_id)
+                    .copied()
+                    .unwrap_or(INITIAL_RATING);
+                // Replaces the request with another match format
+                self.matchmaker.push(msg.user_id, config, rating);
+
+                if let Some(opponent) = self.take_opponent(msg.user_id) {
+                    let room_id = self.create_room(msg.user_id, opponent, config, ctx);
+
+                    // Send message to the opponent about success matchmaking
+                    self.send_to_user(
+                        opponent,
+                        OutgoingClientMessage::MatchmakingSuccess(MatchmakingSuccessPayload {
+                            room: room_id,
+                            opponent: msg.user_id,
+                            config,
+                        }),
+                    );
 
                     Box::pin(fut::ready(Ok(
                         ProcessClientMessageResult::StartMatchmakingResult(
@@ -96,23 +665,210 @@ impl Handler<ProcessClientMessage> for Server {
                                 opponent: Some(opponent),
                                 status: MatchmakingStatus::Found,
                                 room: Some(room_id),
+                                config,
                             },
                         ),
                     )))
                 } else {
-                    self.matchmaking_queue.push_back(msg.user_id);
-
                     Box::pin(fut::ready(Ok(
                         ProcessClientMessageResult::StartMatchmakingResult(
                             StartMatchmakingResultPayload {
                                 opponent: None,
                                 status: MatchmakingStatus::Searching,
                                 room: None,
+                                config,
                             },
                         ),
                     )))
                 }
             }
+            IncomingClientMessage::CancelMatchmaking => {
+                self.remove_from_queue(msg.user_id);
+
+                Box::pin(fut::ready(Ok(
+                    ProcessClientMessageResult::MatchmakingCancelled,
+                )))
+            }
+            IncomingClientMessage::CreatePrivateRoom(config) => {
+                let config = config.unwrap_or(self.room_settings.default_config);
+                if let Err(err) = config.validate() {
+                    return Box::pin(fut::ready(Err(ServerError::from(err))));
+                }
+
+                self.remove_from_queue(msg.user_id);
+                self.remove_pending_private_rooms(msg.user_id);
+
+                let code = self.generate_invite_code();
+                self.private_rooms.insert(
+                    code.clone(),
+                    PrivateRoom {
+                        owner: msg.user_id,
+                        config,
+                        created_at: Instant::now(),
+                        room: None,
+                    },
+                );
+
+                Box::pin(fut::ready(Ok(
+                    ProcessClientMessageResult::PrivateRoomCreated(
+                        PrivateRoomCreatedResultPayload {
+                            code,
+                            expires_at: SystemTime::now() + self.settings.invite_code_ttl,
+                        },
+                    ),
+                )))
+            }
+            IncomingClientMessage::JoinPrivateRoom(payload) => {
+                let code = payload.code.trim().to_uppercase();
+
+                let owner = match self.private_rooms.get(&code) {
+                    None => Err((ErrorCode::InviteCodeNotFound, "No room with such code")),
+                    Some(private_room)
+                        if private_room.is_expired(self.settings.invite_code_ttl) =>
+                    {
+                        Err((ErrorCode::InviteCodeExpired, "The code is expired"))
+                    }
+                    Some(private_room) if private_room.room.is_some() => {
+                        Err((ErrorCode::RoomFull, "The room is full"))
+                    }
+                    Some(private_room) if private_room.owner == msg.user_id => Err((
+                        ErrorCode::CannotJoinOwnRoom,
+                        "You cannot join your own room",
+                    )),
+                    Some(private_room) if !self.connections.contains_key(&private_room.owner) => {
+                        Err((ErrorCode::OpponentGone, "The room owner is offline"))
+                    }
+                    Some(private_room) if self.user_rooms.contains_key(&private_room.owner) => {
+                        Err((
+                            ErrorCode::AlreadyInRoom,
+                            "The room owner is already in a game",
+                        ))
+                    }
+                    Some(private_room) => Ok((private_room.owner, private_room.config)),
+                };
+
+                let (owner, config) = match owner {
+                    Ok(owner) => owner,
+                    Err((code, message)) => {
+                        return Box::pin(fut::ready(Err(ServerError::new(code, message))))
+                    }
+                };
+
+                let room_id = self.create_room(owner, msg.user_id, config, ctx);
+                if let Some(private_room) = self.private_rooms.get_mut(&code) {
+                    private_room.room = Some(room_id);
+                }
+
+                self.send_to_user(
+                    owner,
+                    OutgoingClientMessage::MatchmakingSuccess(MatchmakingSuccessPayload {
+                        room: room_id,
+                        opponent: msg.user_id,
+                        config,
+                    }),
+                );
+
+                Box::pin(fut::ready(Ok(
+                    ProcessClientMessageResult::StartMatchmakingResult(
+                        StartMatchmakingResultPayload {
+                            opponent: Some(owner),
+                            status: MatchmakingStatus::Found,
+                            room: Some(room_id),
+                            config,
+                        },
+                    ),
+                )))
+            }
+            IncomingClientMessage::RequestRematch(payload) => {
+                let finished_room = match self.finished_rooms.get_mut(&payload.room) {
+                    Some(finished_room) if finished_room.users.contains(&msg.user_id) => {
+                        finished_room
+                    }
+                    _ => {
+                        return Box::pin(fut::ready(Err(ServerError::new(
+                            ErrorCode::RematchUnavailable,
+                            "Rematch is not available",
+                        ))))
+                    }
+                };
+
+                let opponent = *finished_room
+                    .users
+                    .iter()
+                    .find(|u| **u != msg.user_id)
+                    .unwrap();
+
+                if !finished_room.rematch_requests.contains(&opponent) {
+                    if !finished_room.rematch_requests.contains(&msg.user_id) {
+                        finished_room.rematch_requests.push(msg.user_id);
+                        self.send_to_user(
+                            opponent,
+                            OutgoingClientMessage::RematchOffered(RematchPayload {
+                                room: payload.room,
+                            }),
+                        );
+                    }
+
+                    return Box::pin(fut::ready(Ok(ProcessClientMessageResult::RematchRequested)));
+                }
+
+                if !self.connections.contains_key(&opponent) {
+                    return Box::pin(fut::ready(Err(ServerError::new(
+                        ErrorCode::OpponentGone,
+                        "Opponent is offline",
+                    ))));
+                }
+                if self.user_rooms.contains_key(&opponent) {
+                    return Box::pin(fut::ready(Err(ServerError::new(
+                        ErrorCode::AlreadyInRoom,
+                        "Opponent is already in another game",
+                    ))));
+                }
+
+                let config = finished_room.config;
+                self.finished_rooms.remove(&payload.room);
+                let room_id = self.create_room(opponent, msg.user_id, config, ctx);
+
+                self.send_to_user(
+                    opponent,
+                    OutgoingClientMessage::MatchmakingSuccess(MatchmakingSuccessPayload {
+                        room: room_id,
+                        opponent: msg.user_id,
+                        config,
+                    }),
+                );
+
+                Box::pin(fut::ready(Ok(
+                    ProcessClientMessageResult::StartMatchmakingResult(
+                        StartMatchmakingResultPayload {
+                            opponent: Some(opponent),
+                            status: MatchmakingStatus::Found,
+                            room: Some(room_id),
+                            config,
+                        },
+                    ),
+                )))
+            }
+            IncomingClientMessage::DeclineRematch(payload) => {
+                match self.finished_rooms.get(&payload.room) {
+                    Some(finished_room) if finished_room.users.contains(&msg.user_id) => {
+                        let finished_room = self.finished_rooms.remove(&payload.room).unwrap();
+                        for user_id in finished_room.rematch_requests {
+                            self.send_to_user(
+                                user_id,
+                                OutgoingClientMessage::RematchDeclined(RematchPayload {
+                                    room: payload.room,
+                                }),
+                            );
+                        }
+                    }
+                    _ => (),
+                }
+
+                Box::pin(fut::ready(Ok(ProcessClientMessageResult::RematchDeclined(
+                    payload.room,
+                ))))
+            }
             IncomingClientMessage::MakeAction(payload) => {
                 if let Some(room) = self.rooms.get(&payload.room) {
                     Box::pin(
@@ -124,9 +880,7 @@ impl Handler<ProcessClientMessage> for Server {
                         .then(move |res, server, _ctx| {
                             if let Err(err) = res {
                                 log::error!("Couldn't send message to room: {}", err);
-                                return fut::ready(Err(ServerError {
-                                    message: "Internal error, try again".to_owned(),
-                                }));
+                                return fut::ready(Err(ServerError::internal()));
                             }
 
                             let res = match res.unwrap() {
@@ -138,33 +892,32 @@ impl Handler<ProcessClientMessage> for Server {
                                                 .iter()
                                                 .find(|u| **u != msg.user_id)
                                                 .unwrap();
-                                            let opponent_connection =
-                                                server.connections.get(&opponent).unwrap();
 
-                                            opponent_connection.do_send(SendClientMessage {
-                                                message: OutgoingClientMessage::from(
+                                            server.send_to_user(
+                                                *opponent,
+                                                OutgoingClientMessage::from(
                                                     ProcessClientMessageResult::MakeActionResult(
                                                         make_action_res.clone(),
                                                     ),
                                                 ),
-                                            })
+                                            );
+                                            // The connection of the sender waits for this reply
+                                            // before handling other messages, so it gets the
+                                            // result first as well
+                                            server.send_round_started(
+                                                payload.room,
+                                                result.users,
+                                                result.next_round_cound.saturating_add(1),
+                                                result.next_round_deadline,
+                                            );
                                         }
-                                        MakeActionResult::GameFinished(result) => {
-                                            let opponent = result
-                                                .users
-                                                .iter()
-                                                .find(|u| **u != msg.user_id)
-                                                .unwrap();
-                                            let opponent_connection =
-                                                server.connections.get(&opponent).unwrap();
-
-                                            opponent_connection.do_send(SendClientMessage {
-                                                message: OutgoingClientMessage::from(
-                                                    ProcessClientMessageResult::MakeActionResult(
-                                                        make_action_res.clone(),
-                                                    ),
+                                        // Reported by the GameOver handler together with rating changes
+                                        MakeActionResult::GameFinished(_) => {
+                                            return fut::ready(Ok(
+                                                ProcessClientMessageResult::MakeActionResult(
+                                                    MakeActionResult::Accepted,
                                                 ),
-                                            })
+                                            ))
                                         }
                                         _ => (),
                                     };
@@ -179,11 +932,222 @@ impl Handler<ProcessClientMessage> for Server {
                         }),
                     )
                 } else {
-                    Box::pin(fut::ready(Err(ServerError {
-                        message: "No such room".to_owned(),
-                    })))
+                    Box::pin(fut::ready(Err(ServerError::new(
+                        ErrorCode::RoomNotFound,
+                        "No such room",
+                    ))))
                 }
             }
+            IncomingClientMessage::GetProfile(payload) => {
+                let user_id = payload
+                    .and_then(|payload| payload.user_id)
+                    .unwrap_or(msg.user_id);
+
+                Box::pin(
+                    self.storage
+                        .send(LoadProfile { user_id })
+                        .into_actor(self)
+                        .map(move |res, _server, _ctx| match res {
+                            Ok(Ok(profile)) => Ok(ProcessClientMessageResult::Profile(
+                                profile.unwrap_or_else(|| Profile::new(user_id)),
+                            )),
+                            Ok(Err(err)) => {
+                                log::error!("Couldn't load profile: {}", err);
+                                Err(ServerError::new(
+                                    ErrorCode::Internal,
+                                    "Couldn't load the profile",
+                                ))
+                            }
+                            Err(err) => {
+                                log::error!("Couldn't send message to storage: {}", err);
+                                Err(ServerError::internal())
+                            }
+                        }),
+                )
+            }
+            IncomingClientMessage::GetLeaderboard(payload) => {
+                let payload = payload.unwrap_or_default();
+                let query = LeaderboardQuery {
+                    user_id: msg.user_id,
+                    scope: payload.scope,
+                    since: payload.period.since(SystemTime::now()),
+                    offset: payload.offset,
+                    limit: payload.limit.min(MAX_LEADERBOARD_LIMIT),
+                };
+
+                Box::pin(
+                    self.storage
+                        .send(LoadLeaderboard { query })
+                        .into_actor(self)
+                        .map(|res, _server, _ctx| match res {
+                            Ok(Ok(leaderboard)) => {
+                                Ok(ProcessClientMessageResult::Leaderboard(leaderboard))
+                            }
+                            Ok(Err(err)) => {
+                                log::error!("Couldn't load leaderboard: {}", err);
+                                Err(ServerError::new(
+                                    ErrorCode::Internal,
+                                    "Couldn't load the leaderboard",
+                                ))
+                            }
+                            Err(err) => {
+                                log::error!("Couldn't send message to storage: {}", err);
+                                Err(ServerError::internal())
+                            }
+                        }),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+    use actix::clock::sleep;
+
+    use super::*;
+    use crate::{
+        room::actor::Action,
+        storage::memory::InMemoryMatchRepository,
+        websockets::client_messages::{JoinPrivateRoomPayload, MakeActionPayload},
+    };
+
+    const ROUND_TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Stands in for a websocket connection and keeps the types of pushed messages.
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SendClientMessage> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: SendClientMessage, _ctx: &mut Self::Context) -> Self::Result {
+            let message = match msg.message {
+                OutgoingClientMessage::RoundStarted(payload) => {
+                    format!("RoundStarted {}", payload.round)
+                }
+                message => serde_json::to_value(message).unwrap()["type"]
+                    .as_str()
+                    .unwrap()
+                    .to_owned(),
+            };
+            self.0.borrow_mut().push(message);
+        }
+    }
+
+    impl Handler<Close> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, _msg: Close, _ctx: &mut Self::Context) -> Self::Result {}
+    }
+
+    fn connect(server: &mut Server, user_id: UserId) -> Rc<RefCell<Vec<String>>> {
+        let messages = Rc::new(RefCell::new(vec![]));
+        let recorder = Recorder(messages.clone()).start();
+        server.connections.insert(
+            user_id,
+            UserConnection {
+                messages: recorder.clone().recipient(),
+                close: recorder.recipient(),
+            },
+        );
+        messages
+    }
+
+    async fn send(
+        server: &Addr<Server>,
+        user_id: UserId,
+        message: IncomingClientMessage,
+    ) -> ProcessClientMessageResult {
+        server
+            .send(ProcessClientMessage { message, user_id })
+            .await
+            .unwrap()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[actix::test]
+    async fn next_round_is_started_after_the_result() {
+        let storage =
+            SyncArbiter::start(1, || Storage::new(Arc::new(InMemoryMatchRepository::new())));
+        let mut server = Server::new(
+            ServerConfig::default(),
+            MatchmakingConfig::default(),
+            RoomSettings {
+                round_timeout: ROUND_TIMEOUT,
+                ..RoomSettings::default()
+            },
+            storage,
+            Metrics::new().unwrap(),
+        );
+        let first = connect(&mut server, 1);
+        let second = connect(&mut server, 2);
+        let server = server.start();
+
+        let ProcessClientMessageResult::PrivateRoomCreated(created) = send(
+            &server,
+            1,
+            IncomingClientMessage::CreatePrivateRoom(Some(RoomConfig {
+                wins_required: 3,
+                ..RoomConfig::default()
+            })),
+        )
+        .await
+        else {
+            panic!("Private room isn't created");
+        };
+        let ProcessClientMessageResult::StartMatchmakingResult(joined) = send(
+            &server,
+            2,
+            IncomingClientMessage::JoinPrivateRoom(JoinPrivateRoomPayload { code: created.code }),
+        )
+        .await
+        else {
+            panic!("Private room isn't joined");
+        };
+        let room = joined.room.unwrap();
+
+        // The second user completes the round, the first one only gets pushed messages
+        for (user_id, action) in [(1, Action::Rock), (2, Action::Scissors)] {
+            send(
+                &server,
+                user_id,
+                IncomingClientMessage::MakeAction(MakeActionPayload { room, action }),
+            )
+            .await;
+        }
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            *first.borrow(),
+            [
+                "MatchmakingSuccess",
+                "RoundStarted 1",
+                "RoundFinished",
+                "RoundStarted 2"
+            ]
+        );
+
+        // Only the first user makes an action, the round is completed by the timer
+        send(
+            &server,
+            1,
+            IncomingClientMessage::MakeAction(MakeActionPayload {
+                room,
+                action: Action::Paper,
+            }),
+        )
+        .await;
+        sleep(ROUND_TIMEOUT).await;
+        for messages in [&first, &second] {
+            assert_eq!(
+                messages.borrow()[messages.borrow().len() - 2..],
+                ["RoundFinished", "RoundStarted 3"]
+            );
         }
     }
 }
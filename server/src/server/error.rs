@@ -1,15 +1,31 @@
 use derive_more::{Display, Error};
 
-use crate::room::error::RoomError;
+use crate::{room::error::RoomError, types::ErrorCode};
 
 #[derive(Debug, Display, Error)]
+#[display(fmt = "{}", message)]
 pub struct ServerError {
+    pub code: ErrorCode,
     pub message: String,
 }
 
+impl ServerError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn internal() -> Self {
+        Self::new(ErrorCode::Internal, "Internal error, try again")
+    }
+}
+
 impl From<RoomError> for ServerError {
     fn from(value: RoomError) -> Self {
         Self {
+            code: value.code,
             message: value.message,
         }
     }
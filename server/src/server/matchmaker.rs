@@ -0,0 +1,251 @@
+use std::time::Instant;
+
+use crate::{config::settings::MatchmakingConfig, room::actor::RoomConfig, types::UserId};
+
+/// Source of the current time, replaced with a fake one to control queue time.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QueuedPlayer {
+    pub user_id: UserId,
+    pub config: RoomConfig,
+    pub rating: i32,
+    pub joined_at: Instant,
+}
+
+/// Pairs players who asked for the same match format and have close ratings.
+/// The longer a player waits the wider the rating band they can be matched within.
+pub struct Matchmaker<C: Clock = SystemClock> {
+    /// Ordered by the time of joining
+    queue: Vec<QueuedPlayer>,
+    initial_rating_band: u32,
+    rating_band_growth_per_second: u32,
+    clock: C,
+}
+
+impl Matchmaker {
+    pub fn new(config: &MatchmakingConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+}
+
+impl<C: Clock> Matchmaker<C> {
+    pub fn with_clock(config: &MatchmakingConfig, clock: C) -> Self {
+        Self {
+            queue: vec![],
+            initial_rating_band: config.initial_rating_band,
+            rating_band_growth_per_second: config.rating_band_growth_per_second,
+            clock,
+        }
+    }
+
+    pub fn contains(&self, user_id: UserId, config: RoomConfig) -> bool {
+        self.queue
+            .iter()
+            .any(|player| player.user_id == user_id && player.config == config)
+    }
+
+    /// Puts the player to the end of the queue, replacing the previous request of the same player.
+    pub fn push(&mut self, user_id: UserId, config: RoomConfig, rating: i32) {
+        self.remove(user_id);
+        self.queue.push(QueuedPlayer {
+            user_id,
+            config,
+            rating,
+            joined_at: self.clock.now(),
+        });
+    }
+
+    pub fn remove(&mut self, user_id: UserId) -> Option<QueuedPlayer> {
+        let position = self
+            .queue
+            .iter()
+            .position(|player| player.user_id == user_id)?;
+
+        Some(self.queue.remove(position))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Empties the queue.
+    pub fn clear(&mut self) -> Vec<QueuedPlayer> {
+        std::mem::take(&mut self.queue)
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&QueuedPlayer) -> bool) {
+        self.queue.retain(f);
+    }
+
+    /// Rating difference the player accepts after waiting in the queue.
+    pub fn rating_band(&self, player: &QueuedPlayer) -> u32 {
+        let waited = self
+            .clock
+            .now()
+            .saturating_duration_since(player.joined_at)
+            .as_secs() as u32;
+
+        self.initial_rating_band
+            .saturating_add(waited.saturating_mul(self.rating_band_growth_per_second))
+    }
+
+    /// Takes the queued player and the closest rated opponent out of the queue.
+    /// The wider band of the two decides, so a long waiting player can be matched with a newcomer.
+    pub fn take_match(&mut self, user_id: UserId) -> Option<(QueuedPlayer, QueuedPlayer)> {
+        let player = *self.queue.iter().find(|player| player.user_id == user_id)?;
+        let player_band = self.rating_band(&player);
+
+        let opponent = self
+            .queue
+            .iter()
+            .filter(|opponent| {
+                opponent.user_id != player.user_id
+                    && opponent.config == player.config
+                    && player.rating.abs_diff(opponent.rating)
+                        <= player_band.max(self.rating_band(opponent))
+            })
+            .min_by_key(|opponent| player.rating.abs_diff(opponent.rating))?
+            .user_id;
+
+        Some((self.remove(user_id)?, self.remove(opponent)?))
+    }
+
+    /// Matches as many queued players as possible, the longest waiting first.
+    pub fn take_matches(&mut self) -> Vec<(QueuedPlayer, QueuedPlayer)> {
+        let users = self
+            .queue
+            .iter()
+            .map(|player| player.user_id)
+            .collect::<Vec<UserId>>();
+
+        users
+            .into_iter()
+            .filter_map(|user_id| self.take_match(user_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use super::*;
+
+    /// Clock moved forward by the test, clones share the time.
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<Instant>>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self(Rc::new(Cell::new(Instant::now())))
+        }
+
+        fn advance(&self, secs: u64) {
+            self.0.set(self.0.get() + Duration::from_secs(secs));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    fn matchmaker() -> (Matchmaker<FakeClock>, FakeClock) {
+        let clock = FakeClock::new();
+        let config = MatchmakingConfig {
+            initial_rating_band: 100,
+            rating_band_growth_per_second: 10,
+            ..MatchmakingConfig::default()
+        };
+
+        (Matchmaker::with_clock(&config, clock.clone()), clock)
+    }
+
+    fn users(pair: Option<(QueuedPlayer, QueuedPlayer)>) -> Option<(UserId, UserId)> {
+        pair.map(|(player, opponent)| (player.user_id, opponent.user_id))
+    }
+
+    #[test]
+    fn band_widens_over_time() {
+        let (mut matchmaker, clock) = matchmaker();
+        matchmaker.push(1, RoomConfig::default(), 1200);
+        let player = matchmaker.queue[0];
+        assert_eq!(matchmaker.rating_band(&player), 100);
+
+        clock.advance(5);
+        assert_eq!(matchmaker.rating_band(&player), 150);
+
+        matchmaker.push(2, RoomConfig::default(), 1380);
+        assert_eq!(users(matchmaker.take_match(2)), None);
+
+        clock.advance(3);
+        assert_eq!(users(matchmaker.take_match(2)), Some((2, 1)));
+        assert_eq!(matchmaker.len(), 0);
+    }
+
+    #[test]
+    fn pairs_same_format_only() {
+        let (mut matchmaker, _) = matchmaker();
+        let best_of_five = RoomConfig {
+            wins_required: 3,
+            ..RoomConfig::default()
+        };
+        matchmaker.push(1, RoomConfig::default(), 1200);
+        matchmaker.push(2, best_of_five, 1200);
+        assert_eq!(users(matchmaker.take_match(2)), None);
+        assert!(matchmaker.take_matches().is_empty());
+
+        matchmaker.push(3, best_of_five, 1250);
+        assert_eq!(users(matchmaker.take_match(3)), Some((3, 2)));
+        assert!(matchmaker.contains(1, RoomConfig::default()));
+    }
+
+    #[test]
+    fn take_matches_pairs_longest_waiting_first() {
+        let (mut matchmaker, clock) = matchmaker();
+        matchmaker.push(1, RoomConfig::default(), 1200);
+        clock.advance(1);
+        matchmaker.push(2, RoomConfig::default(), 1300);
+        clock.advance(1);
+        // Closer to player 2, but player 1 has waited longer
+        matchmaker.push(3, RoomConfig::default(), 1290);
+
+        assert_eq!(
+            matchmaker
+                .take_matches()
+                .into_iter()
+                .map(|pair| users(Some(pair)).unwrap())
+                .collect::<Vec<(UserId, UserId)>>(),
+            vec![(1, 3)]
+        );
+        assert!(matchmaker.contains(2, RoomConfig::default()));
+    }
+
+    #[test]
+    fn wider_band_of_the_two_decides() {
+        let (mut matchmaker, clock) = matchmaker();
+        matchmaker.push(1, RoomConfig::default(), 1200);
+        clock.advance(10);
+
+        // The newcomer's band is 100, the waiting player's one is 200
+        matchmaker.push(2, RoomConfig::default(), 1390);
+        assert_eq!(users(matchmaker.take_match(2)), Some((2, 1)));
+
+        matchmaker.push(3, RoomConfig::default(), 1200);
+        clock.advance(10);
+        matchmaker.push(4, RoomConfig::default(), 1410);
+        assert_eq!(users(matchmaker.take_match(4)), None);
+    }
+}
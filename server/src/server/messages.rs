@@ -1,10 +1,16 @@
-use actix::{Addr, Message};
+use std::time::{Duration, SystemTime};
+
+use actix::{Addr, Message, MessageResponse};
 use uuid::Uuid;
 
 use crate::{
-    room::messages::MakeActionResult,
+    room::{
+        actor::RoomConfig,
+        messages::{GameFinishedResult, MakeActionResult},
+    },
     server::error::ServerError,
-    types::UserId,
+    storage::repository::{GameRecord, Leaderboard, Profile},
+    types::{RoomId, UserId},
     websockets::{client_messages::IncomingClientMessage, ws::Connection},
 };
 
@@ -13,6 +19,56 @@ use crate::{
 pub struct AttachConnection {
     pub connection: Addr<Connection>,
     pub user_id: UserId,
+    pub display_name: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DetachConnection {
+    pub connection: Addr<Connection>,
+    pub user_id: UserId,
+}
+
+/// Stops new games, waits for the running ones within the grace period and closes all connections.
+/// The reply comes once the process can be stopped.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown {
+    pub grace: Duration,
+}
+
+/// Answered right away, so a reply in time means the server isn't stuck.
+#[derive(Message)]
+#[rtype(result = "ServerStatus")]
+pub struct GetStatus;
+
+#[derive(MessageResponse)]
+pub struct ServerStatus {
+    pub shutting_down: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct GameOver {
+    pub room_id: RoomId,
+    pub result: GameFinishedResult,
+    pub record: GameRecord,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoundStarted {
+    pub room_id: RoomId,
+    pub users: [UserId; 2],
+    pub round: u8,
+    pub deadline: SystemTime,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoundTimedOut {
+    pub room_id: RoomId,
+    pub result: MakeActionResult,
 }
 
 #[derive(Message)]
@@ -24,7 +80,13 @@ pub struct ProcessClientMessage {
 
 pub enum ProcessClientMessageResult {
     StartMatchmakingResult(StartMatchmakingResultPayload),
+    MatchmakingCancelled,
+    PrivateRoomCreated(PrivateRoomCreatedResultPayload),
+    RematchRequested,
+    RematchDeclined(RoomId),
     MakeActionResult(MakeActionResult),
+    Profile(Profile),
+    Leaderboard(Leaderboard),
 }
 
 pub enum MatchmakingStatus {
@@ -36,4 +98,10 @@ pub struct StartMatchmakingResultPayload {
     pub opponent: Option<UserId>,
     pub room: Option<Uuid>,
     pub status: MatchmakingStatus,
+    pub config: RoomConfig,
+}
+
+pub struct PrivateRoomCreatedResultPayload {
+    pub code: String,
+    pub expires_at: SystemTime,
 }
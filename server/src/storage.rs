@@ -0,0 +1,6 @@
+pub mod actor;
+pub mod error;
+pub mod memory;
+pub mod messages;
+pub mod repository;
+pub mod sqlite;
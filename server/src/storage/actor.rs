@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use actix::{Actor, Handler, SyncContext};
+
+use crate::{
+    rating::{apply_game, Outcome},
+    room::messages::FinishReason,
+};
+
+use super::{
+    error::StorageError,
+    messages::{
+        CheckStorage, LoadGame, LoadGames, LoadLeaderboard, LoadProfile, RatingChange, SaveGame,
+        SaveUser,
+    },
+    repository::{GameRecord, GameSummary, Leaderboard, MatchRepository, Profile},
+};
+
+/// Runs repository calls on a separate thread so other actors are never blocked by the database.
+pub struct Storage {
+    repository: Arc<dyn MatchRepository>,
+}
+
+impl Storage {
+    pub fn new(repository: Arc<dyn MatchRepository>) -> Self {
+        Self { repository }
+    }
+}
+
+impl Actor for Storage {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<SaveGame> for Storage {
+    type Result = Result<Vec<RatingChange>, StorageError>;
+
+    fn handle(&mut self, msg: SaveGame, _ctx: &mut Self::Context) -> Self::Result {
+        // Nobody has played an abandoned game, it's kept in the history without affecting profiles
+        if msg.game.reason == FinishReason::Abandoned {
+            self.repository.save_game(&msg.game, &[])?;
+            return Ok(vec![]);
+        }
+
+        let mut profiles = msg
+            .game
+            .users
+            .iter()
+            .map(|user_id| {
+                let profile = self.repository.get_profile(*user_id)?;
+                Ok(profile.unwrap_or_else(|| Profile::new(*user_id)))
+            })
+            .collect::<Result<Vec<Profile>, StorageError>>()?;
+        let ratings = profiles
+            .iter()
+            .map(|profile| profile.rating)
+            .collect::<Vec<i32>>();
+
+        let changes = profiles
+            .iter_mut()
+            .zip(ratings.iter().rev())
+            .map(|(profile, opponent_rating)| {
+                let outcome = match msg.game.winner {
+                    None => Outcome::Draw,
+                    Some(winner) if winner == profile.user_id => Outcome::Win,
+                    Some(_) => Outcome::Loss,
+                };
+                let delta = apply_game(profile, *opponent_rating, outcome);
+
+                RatingChange {
+                    user_id: profile.user_id,
+                    rating: profile.rating,
+                    delta,
+                }
+            })
+            .collect();
+
+        self.repository.save_game(&msg.game, &profiles)?;
+
+        Ok(changes)
+    }
+}
+
+impl Handler<LoadProfile> for Storage {
+    type Result = Result<Option<Profile>, StorageError>;
+
+    fn handle(&mut self, msg: LoadProfile, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository.get_profile(msg.user_id)
+    }
+}
+
+impl Handler<SaveUser> for Storage {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: SaveUser, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository.save_user(msg.user_id, &msg.display_name)
+    }
+}
+
+impl Handler<LoadLeaderboard> for Storage {
+    type Result = Result<Leaderboard, StorageError>;
+
+    fn handle(&mut self, msg: LoadLeaderboard, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository.leaderboard(&msg.query)
+    }
+}
+
+impl Handler<LoadGames> for Storage {
+    type Result = Result<Vec<GameSummary>, StorageError>;
+
+    fn handle(&mut self, msg: LoadGames, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository
+            .games(msg.user_id, msg.before.as_ref(), msg.limit)
+    }
+}
+
+impl Handler<LoadGame> for Storage {
+    type Result = Result<Option<GameRecord>, StorageError>;
+
+    fn handle(&mut self, msg: LoadGame, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository.game(msg.id)
+    }
+}
+
+impl Handler<CheckStorage> for Storage {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, _msg: CheckStorage, _ctx: &mut Self::Context) -> Self::Result {
+        self.repository.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use actix::{Addr, SyncArbiter};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{room::actor::RoomConfig, storage::memory::InMemoryMatchRepository, types::UserId};
+
+    fn start() -> (Arc<InMemoryMatchRepository>, Addr<Storage>) {
+        let repository = Arc::new(InMemoryMatchRepository::new());
+        let storage = SyncArbiter::start(1, {
+            let repository = repository.clone();
+            move || Storage::new(repository.clone())
+        });
+        (repository, storage)
+    }
+
+    fn game(winner: Option<UserId>, reason: FinishReason) -> GameRecord {
+        GameRecord {
+            id: Uuid::new_v4(),
+            users: [1, 2],
+            winner,
+            reason,
+            config: RoomConfig::default(),
+            started_at: SystemTime::now(),
+            finished_at: SystemTime::now(),
+            rounds: vec![],
+        }
+    }
+
+    #[actix::test]
+    async fn completed_game_is_rated() {
+        let (repository, storage) = start();
+
+        let changes = storage
+            .send(SaveGame {
+                game: game(Some(1), FinishReason::Completed),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| (change.user_id, change.delta))
+                .collect::<Vec<_>>(),
+            [(1, 16), (2, -16)]
+        );
+        let winner = repository.get_profile(1).unwrap().unwrap();
+        assert_eq!((winner.games, winner.wins, winner.rating), (1, 1, 1216));
+    }
+
+    #[actix::test]
+    async fn abandoned_game_is_not_rated() {
+        let (repository, storage) = start();
+        let game = game(None, FinishReason::Abandoned);
+        let id = game.id;
+
+        let changes = storage.send(SaveGame { game }).await.unwrap().unwrap();
+
+        assert!(changes.is_empty());
+        for user_id in [1, 2] {
+            assert_eq!(repository.get_profile(user_id).unwrap(), None);
+        }
+        assert!(repository.game(id).unwrap().is_some());
+    }
+}
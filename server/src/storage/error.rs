@@ -0,0 +1,22 @@
+use derive_more::{Display, Error};
+
+#[derive(Debug, Display, Error)]
+pub struct StorageError {
+    pub message: String,
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(value: rusqlite::Error) -> Self {
+        Self {
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(value: serde_json::Error) -> Self {
+        Self {
+            message: value.to_string(),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::types::{RoomId, UserId};
+
+use super::{
+    error::StorageError,
+    repository::{
+        GameRecord, GameSummary, GamesCursor, Leaderboard, LeaderboardEntry, LeaderboardQuery,
+        LeaderboardScope, MatchRepository, Profile,
+    },
+};
+
+/// Keeps games in memory only. Everything is lost on restart.
+pub struct InMemoryMatchRepository {
+    games: Mutex<HashMap<RoomId, GameRecord>>,
+    profiles: Mutex<HashMap<UserId, Profile>>,
+    display_names: Mutex<HashMap<UserId, String>>,
+}
+
+impl InMemoryMatchRepository {
+    pub fn new() -> Self {
+        Self {
+            games: Mutex::new(HashMap::new()),
+            profiles: Mutex::new(HashMap::new()),
+            display_names: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MatchRepository for InMemoryMatchRepository {
+    fn save_game(&self, game: &GameRecord, profiles: &[Profile]) -> Result<(), StorageError> {
+        self.games
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(game.id, game.clone());
+
+        let mut stored_profiles = self.profiles.lock().unwrap_or_else(|err| err.into_inner());
+        for profile in profiles {
+            stored_profiles.insert(profile.user_id, profile.clone());
+        }
+
+        Ok(())
+    }
+
+    fn get_profile(&self, user_id: UserId) -> Result<Option<Profile>, StorageError> {
+        Ok(self
+            .profiles
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(&user_id)
+            .cloned())
+    }
+
+    fn save_user(&self, user_id: UserId, display_name: &str) -> Result<(), StorageError> {
+        self.display_names
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(user_id, display_name.to_owned());
+        Ok(())
+    }
+
+    fn leaderboard(&self, query: &LeaderboardQuery) -> Result<Leaderboard, StorageError> {
+        let games = self.games.lock().unwrap_or_else(|err| err.into_inner());
+        let profiles = self.profiles.lock().unwrap_or_else(|err| err.into_inner());
+        let display_names = self
+            .display_names
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let friends = games
+            .values()
+            .filter(|game| game.users.contains(&query.user_id))
+            .flat_map(|game| game.users)
+            .chain([query.user_id])
+            .collect::<HashSet<UserId>>();
+        let is_in_scope = |user_id: &UserId| match query.scope {
+            LeaderboardScope::Global => true,
+            LeaderboardScope::Friends => friends.contains(user_id),
+        };
+
+        let mut board = match query.since {
+            None => profiles
+                .values()
+                .filter(|profile| profile.games > 0 && is_in_scope(&profile.user_id))
+                .map(|profile| (profile.user_id, profile.rating, profile.wins))
+                .collect::<Vec<(UserId, i32, u32)>>(),
+            Some(since) => {
+                let mut wins = HashMap::<UserId, u32>::new();
+                for game in games.values().filter(|game| game.finished_at >= since) {
+                    if let Some(winner) = game.winner {
+                        *wins.entry(winner).or_default() += 1;
+                    }
+                }
+
+                wins.into_iter()
+                    .filter(|(user_id, _)| is_in_scope(user_id))
+                    .filter_map(|(user_id, wins)| {
+                        Some((user_id, profiles.get(&user_id)?.rating, wins))
+                    })
+                    .collect()
+            }
+        };
+
+        match query.since {
+            None => board.sort_by_key(|(user_id, rating, _)| (-rating, *user_id)),
+            Some(_) => {
+                board.sort_by_key(|(user_id, rating, wins)| (Reverse(*wins), -rating, *user_id))
+            }
+        }
+
+        let entries = board
+            .into_iter()
+            .enumerate()
+            .map(|(index, (user_id, rating, wins))| LeaderboardEntry {
+                rank: index as u64 + 1,
+                user_id,
+                display_name: display_names.get(&user_id).cloned(),
+                rating,
+                wins,
+            })
+            .collect::<Vec<LeaderboardEntry>>();
+        let me = entries
+            .iter()
+            .find(|entry| entry.user_id == query.user_id)
+            .cloned();
+
+        Ok(Leaderboard {
+            entries: entries
+                .into_iter()
+                .skip(query.offset as usize)
+                .take(query.limit as usize)
+                .collect(),
+            me,
+        })
+    }
+
+    fn games(
+        &self,
+        user_id: UserId,
+        before: Option<&GamesCursor>,
+        limit: u32,
+    ) -> Result<Vec<GameSummary>, StorageError> {
+        let games = self.games.lock().unwrap_or_else(|err| err.into_inner());
+
+        let mut summaries = games
+            .values()
+            .filter(|game| game.users.contains(&user_id))
+            .filter(|game| match before {
+                Some(cursor) => (game.finished_at, game.id) < (cursor.finished_at, cursor.id),
+                None => true,
+            })
+            .map(GameSummary::from)
+            .collect::<Vec<GameSummary>>();
+        summaries.sort_by_key(|game| Reverse((game.finished_at, game.id)));
+        summaries.truncate(limit as usize);
+
+        Ok(summaries)
+    }
+
+    fn game(&self, id: RoomId) -> Result<Option<GameRecord>, StorageError> {
+        Ok(self
+            .games
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(&id)
+            .cloned())
+    }
+
+    fn check(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
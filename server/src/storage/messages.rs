@@ -0,0 +1,61 @@
+use actix::Message;
+
+use crate::types::{RoomId, UserId};
+
+use super::{
+    error::StorageError,
+    repository::{GameRecord, GameSummary, GamesCursor, Leaderboard, LeaderboardQuery, Profile},
+};
+
+/// Saves the game and updates ratings of both players unless the game is abandoned.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<RatingChange>, StorageError>")]
+pub struct SaveGame {
+    pub game: GameRecord,
+}
+
+/// Loads the profile, `None` if the user hasn't finished a game yet.
+#[derive(Message)]
+#[rtype(result = "Result<Option<Profile>, StorageError>")]
+pub struct LoadProfile {
+    pub user_id: UserId,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), StorageError>")]
+pub struct SaveUser {
+    pub user_id: UserId,
+    pub display_name: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Leaderboard, StorageError>")]
+pub struct LoadLeaderboard {
+    pub query: LeaderboardQuery,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<GameSummary>, StorageError>")]
+pub struct LoadGames {
+    pub user_id: UserId,
+    pub before: Option<GamesCursor>,
+    pub limit: u32,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Option<GameRecord>, StorageError>")]
+pub struct LoadGame {
+    pub id: RoomId,
+}
+
+pub struct RatingChange {
+    pub user_id: UserId,
+    /// The new rating
+    pub rating: i32,
+    pub delta: i32,
+}
+
+/// Checks that the storage is reachable.
+#[derive(Message)]
+#[rtype(result = "Result<(), StorageError>")]
+pub struct CheckStorage;
@@ -0,0 +1,455 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rating::INITIAL_RATING,
+    room::{
+        actor::{Action, RoomConfig},
+        messages::FinishReason,
+    },
+    types::{RoomId, UserId},
+};
+
+use super::error::StorageError;
+
+/// Persistent storage of finished games and players' profiles.
+pub trait MatchRepository: Send + Sync {
+    /// Saves the game together with the profiles updated by its result.
+    fn save_game(&self, game: &GameRecord, profiles: &[Profile]) -> Result<(), StorageError>;
+
+    /// `None` if the user hasn't finished a game yet.
+    fn get_profile(&self, user_id: UserId) -> Result<Option<Profile>, StorageError>;
+
+    /// Remembers the name shown for the user on leaderboards.
+    fn save_user(&self, user_id: UserId, display_name: &str) -> Result<(), StorageError>;
+
+    fn leaderboard(&self, query: &LeaderboardQuery) -> Result<Leaderboard, StorageError>;
+
+    /// Games of the user finished before the cursor, the most recent first.
+    fn games(
+        &self,
+        user_id: UserId,
+        before: Option<&GamesCursor>,
+        limit: u32,
+    ) -> Result<Vec<GameSummary>, StorageError>;
+
+    /// The game with all rounds and moves.
+    fn game(&self, id: RoomId) -> Result<Option<GameRecord>, StorageError>;
+
+    /// Fails if the storage can't be queried.
+    fn check(&self) -> Result<(), StorageError>;
+}
+
+/// Position in the list of games ordered by finish time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GamesCursor {
+    pub finished_at: SystemTime,
+    pub id: RoomId,
+}
+
+impl GamesCursor {
+    pub fn after(game: &GameSummary) -> Self {
+        Self {
+            finished_at: game.finished_at,
+            id: game.id,
+        }
+    }
+
+    /// Clients treat the cursor as an opaque string.
+    pub fn encode(&self) -> String {
+        let millis = self
+            .finished_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        format!("{}_{}", millis, self.id.simple())
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let (millis, id) = cursor.split_once('_')?;
+
+        Some(Self {
+            finished_at: UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?),
+            id: RoomId::parse_str(id).ok()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeaderboardScope {
+    #[default]
+    Global,
+    /// The user and everyone they have played with
+    Friends,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeaderboardPeriod {
+    /// Players are ranked by rating
+    #[default]
+    AllTime,
+    /// Players are ranked by wins in the last 7 days
+    Weekly,
+    /// Players are ranked by wins in the last 24 hours
+    Daily,
+}
+
+impl LeaderboardPeriod {
+    /// Start of the period, `None` for all time.
+    pub fn since(&self, now: SystemTime) -> Option<SystemTime> {
+        match self {
+            LeaderboardPeriod::AllTime => None,
+            LeaderboardPeriod::Weekly => Some(now - Duration::from_secs(7 * 24 * 60 * 60)),
+            LeaderboardPeriod::Daily => Some(now - Duration::from_secs(24 * 60 * 60)),
+        }
+    }
+}
+
+pub struct LeaderboardQuery {
+    /// The user asking, their own entry is returned even when it's outside the page
+    pub user_id: UserId,
+    pub scope: LeaderboardScope,
+    /// Only wins after this time are counted, ranking is by rating when it's `None`
+    pub since: Option<SystemTime>,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeaderboardEntry {
+    /// Starts from 1
+    pub rank: u64,
+    pub user_id: UserId,
+    pub display_name: Option<String>,
+    pub rating: i32,
+    pub wins: u32,
+}
+
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+    /// `None` if the user isn't ranked yet
+    pub me: Option<LeaderboardEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub user_id: UserId,
+    pub rating: i32,
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Profile {
+    pub fn new(user_id: UserId) -> Self {
+        Self {
+            user_id,
+            rating: INITIAL_RATING,
+            games: 0,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub id: RoomId,
+    pub users: [UserId; 2],
+    pub winner: Option<UserId>,
+    pub reason: FinishReason,
+    pub config: RoomConfig,
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+    pub rounds: Vec<RoundRecord>,
+}
+
+/// A game without rounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSummary {
+    pub id: RoomId,
+    pub users: [UserId; 2],
+    pub winner: Option<UserId>,
+    pub reason: FinishReason,
+    pub config: RoomConfig,
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+}
+
+impl From<&GameRecord> for GameSummary {
+    fn from(value: &GameRecord) -> Self {
+        Self {
+            id: value.id,
+            users: value.users,
+            winner: value.winner,
+            reason: value.reason,
+            config: value.config,
+            started_at: value.started_at,
+            finished_at: value.finished_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundRecord {
+    /// Starts from 1
+    pub number: u8,
+    pub winner: Option<UserId>,
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+    pub moves: Vec<MoveRecord>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveRecord {
+    pub user_id: UserId,
+    pub action: Action,
+    pub made_at: SystemTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{memory::InMemoryMatchRepository, sqlite::SqliteMatchRepository};
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn repositories() -> Vec<(&'static str, Box<dyn MatchRepository>)> {
+        let sqlite = SqliteMatchRepository::open(":memory:").unwrap();
+        sqlite.migrate().unwrap();
+
+        vec![
+            ("memory", Box::new(InMemoryMatchRepository::new())),
+            ("sqlite", Box::new(sqlite)),
+        ]
+    }
+
+    fn days_ago(days: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(NOW - days * DAY)
+    }
+
+    fn game(id: u128, users: [UserId; 2], winner: Option<UserId>, days: u64) -> GameRecord {
+        GameRecord {
+            id: RoomId::from_u128(id),
+            users,
+            winner,
+            reason: FinishReason::Completed,
+            config: RoomConfig::default(),
+            started_at: days_ago(days),
+            finished_at: days_ago(days),
+            rounds: vec![],
+        }
+    }
+
+    fn profile(user_id: UserId, rating: i32, games: u32, wins: u32) -> Profile {
+        Profile {
+            rating,
+            games,
+            wins,
+            ..Profile::new(user_id)
+        }
+    }
+
+    /// Ties in rating and wins check the tie-breakers, user 6 has never finished a game.
+    fn fill(repository: &dyn MatchRepository) {
+        let games = [
+            game(1, [1, 2], Some(1), 0),
+            game(2, [1, 3], Some(3), 2),
+            game(3, [2, 4], Some(4), 3),
+            game(4, [4, 5], None, 10),
+            game(5, [2, 5], Some(5), 20),
+            game(6, [1, 4], Some(1), 30),
+            game(8, [1, 5], None, 30),
+        ];
+        for game in &games {
+            repository.save_game(game, &[]).unwrap();
+        }
+
+        let profiles = [
+            profile(1, 1250, 3, 2),
+            profile(2, 1200, 3, 0),
+            profile(3, 1216, 1, 1),
+            profile(4, 1216, 4, 1),
+            profile(5, 1190, 2, 1),
+        ];
+        repository
+            .save_game(&game(7, [1, 2], None, 40), &profiles)
+            .unwrap();
+
+        for (user_id, display_name) in [(1, "Ann"), (3, "Bob"), (6, "Eve")] {
+            repository.save_user(user_id, display_name).unwrap();
+        }
+    }
+
+    fn entry(
+        rank: u64,
+        user_id: UserId,
+        display_name: Option<&str>,
+        rating: i32,
+        wins: u32,
+    ) -> LeaderboardEntry {
+        LeaderboardEntry {
+            rank,
+            user_id,
+            display_name: display_name.map(str::to_owned),
+            rating,
+            wins,
+        }
+    }
+
+    #[test]
+    fn profiles_exist_after_a_finished_game() {
+        for (name, repository) in repositories() {
+            fill(repository.as_ref());
+
+            assert_eq!(
+                repository.get_profile(3).unwrap(),
+                Some(profile(3, 1216, 1, 1)),
+                "{}",
+                name
+            );
+            // Named, but has never finished a game
+            assert_eq!(repository.get_profile(6).unwrap(), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn games_are_paginated_by_cursor() {
+        for (name, repository) in repositories() {
+            fill(repository.as_ref());
+
+            let mut pages = vec![];
+            let mut cursor = None;
+            loop {
+                let page = repository.games(1, cursor.as_ref(), 2).unwrap();
+                if page.is_empty() {
+                    break;
+                }
+                // Clients get the cursor as a string
+                cursor = page
+                    .last()
+                    .and_then(|game| GamesCursor::decode(&GamesCursor::after(game).encode()));
+                pages.push(
+                    page.iter()
+                        .map(|game| game.id.as_u128())
+                        .collect::<Vec<u128>>(),
+                );
+            }
+
+            // Games 6 and 8 have finished at the same time
+            assert_eq!(pages, vec![vec![1, 2], vec![8, 6], vec![7]], "{}", name);
+            assert!(
+                repository.games(6, None, 10).unwrap().is_empty(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn leaderboards_are_the_same() {
+        let repositories = repositories();
+        for (_, repository) in &repositories {
+            fill(repository.as_ref());
+        }
+
+        for scope in [LeaderboardScope::Global, LeaderboardScope::Friends] {
+            for period in [
+                LeaderboardPeriod::AllTime,
+                LeaderboardPeriod::Weekly,
+                LeaderboardPeriod::Daily,
+            ] {
+                for user_id in [1, 2, 5, 6] {
+                    for (offset, limit) in [(0, 10), (1, 2), (10, 5)] {
+                        let query = LeaderboardQuery {
+                            user_id,
+                            scope,
+                            since: period.since(days_ago(0)),
+                            offset,
+                            limit,
+                        };
+                        let boards = repositories
+                            .iter()
+                            .map(|(_, repository)| {
+                                let board = repository.leaderboard(&query).unwrap();
+                                (board.entries, board.me)
+                            })
+                            .collect::<Vec<_>>();
+
+                        assert_eq!(
+                            boards[0], boards[1],
+                            "{:?} {:?} board of user {} from {} by {}",
+                            scope, period, user_id, offset, limit
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn all_time_leaderboard_is_ranked_by_rating() {
+        for (name, repository) in repositories() {
+            fill(repository.as_ref());
+            let board = repository
+                .leaderboard(&LeaderboardQuery {
+                    user_id: 4,
+                    scope: LeaderboardScope::Global,
+                    since: None,
+                    offset: 0,
+                    limit: 10,
+                })
+                .unwrap();
+
+            assert_eq!(
+                board.entries,
+                vec![
+                    entry(1, 1, Some("Ann"), 1250, 2),
+                    entry(2, 3, Some("Bob"), 1216, 1),
+                    entry(3, 4, None, 1216, 1),
+                    entry(4, 2, None, 1200, 0),
+                    entry(5, 5, None, 1190, 1),
+                ],
+                "{}",
+                name
+            );
+            assert_eq!(board.me, Some(entry(3, 4, None, 1216, 1)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn weekly_leaderboard_is_ranked_by_wins() {
+        for (name, repository) in repositories() {
+            fill(repository.as_ref());
+            let board = repository
+                .leaderboard(&LeaderboardQuery {
+                    user_id: 2,
+                    scope: LeaderboardScope::Global,
+                    since: LeaderboardPeriod::Weekly.since(days_ago(0)),
+                    offset: 0,
+                    limit: 10,
+                })
+                .unwrap();
+
+            // Everyone has one win, 3 and 4 have the same rating too
+            assert_eq!(
+                board.entries,
+                vec![
+                    entry(1, 1, Some("Ann"), 1250, 1),
+                    entry(2, 3, Some("Bob"), 1216, 1),
+                    entry(3, 4, None, 1216, 1),
+                ],
+                "{}",
+                name
+            );
+            assert!(board.me.is_none(), "{}", name);
+        }
+    }
+}
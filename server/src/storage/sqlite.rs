@@ -0,0 +1,559 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Row, Statement, ToSql};
+
+use crate::types::{RoomId, UserId};
+
+use super::{
+    error::StorageError,
+    repository::{
+        GameRecord, GameSummary, GamesCursor, Leaderboard, LeaderboardEntry, LeaderboardQuery,
+        LeaderboardScope, MatchRepository, MoveRecord, Profile, RoundRecord,
+    },
+};
+
+/// Schema migrations. A migration is applied once, the number of applied migrations
+/// is kept in `PRAGMA user_version`. Never change a released migration, add a new one.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE games (
+        id TEXT PRIMARY KEY,
+        first_user INTEGER NOT NULL,
+        second_user INTEGER NOT NULL,
+        winner INTEGER,
+        reason TEXT NOT NULL,
+        config TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL
+    );
+    CREATE INDEX games_first_user ON games (first_user, finished_at);
+    CREATE INDEX games_second_user ON games (second_user, finished_at);
+    CREATE INDEX games_finished_at ON games (finished_at);
+
+    CREATE TABLE rounds (
+        game_id TEXT NOT NULL REFERENCES games (id),
+        number INTEGER NOT NULL,
+        winner INTEGER,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        PRIMARY KEY (game_id, number)
+    );
+
+    CREATE TABLE moves (
+        game_id TEXT NOT NULL,
+        round_number INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        made_at INTEGER NOT NULL,
+        PRIMARY KEY (game_id, round_number, user_id),
+        FOREIGN KEY (game_id, round_number) REFERENCES rounds (game_id, number)
+    );
+"#,
+    r#"
+    CREATE TABLE profiles (
+        user_id INTEGER PRIMARY KEY,
+        rating INTEGER NOT NULL,
+        games INTEGER NOT NULL,
+        wins INTEGER NOT NULL,
+        losses INTEGER NOT NULL,
+        draws INTEGER NOT NULL
+    );
+    CREATE INDEX profiles_rating ON profiles (rating DESC);
+"#,
+    r#"
+    CREATE TABLE users (
+        user_id INTEGER PRIMARY KEY,
+        display_name TEXT NOT NULL
+    );
+    CREATE INDEX games_winner ON games (winner, finished_at);
+"#,
+];
+
+/// Players the user has played with, and the user themselves.
+const FRIENDS_FILTER: &str = "WHERE user_id = :user_id
+    OR user_id IN (SELECT second_user FROM games WHERE first_user = :user_id)
+    OR user_id IN (SELECT first_user FROM games WHERE second_user = :user_id)";
+
+/// Players ranked by rating.
+const ALL_TIME_BOARD: &str = "SELECT user_id, rating, wins FROM profiles WHERE games > 0";
+
+/// Players ranked by wins since `:since`.
+const PERIOD_BOARD: &str = "SELECT profiles.user_id, profiles.rating, period_wins.wins
+    FROM profiles
+    JOIN (
+        SELECT winner AS user_id, COUNT(*) AS wins FROM games
+        WHERE winner IS NOT NULL AND finished_at >= :since
+        GROUP BY winner
+    ) AS period_wins ON period_wins.user_id = profiles.user_id";
+
+/// Stores games in an embedded SQLite database.
+pub struct SqliteMatchRepository {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteMatchRepository {
+    pub fn open(path: &str) -> Result<Self, StorageError> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn migrate(&self) -> Result<(), StorageError> {
+        let mut connection = self.connection();
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index + 1)?;
+            transaction.commit()?;
+
+            log::info!("Applied database migration {}", index + 1);
+        }
+
+        Ok(())
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis as u64)
+}
+
+const GAME_COLUMNS: &str =
+    "id, first_user, second_user, winner, reason, config, started_at, finished_at";
+
+fn game_summary(row: &Row) -> Result<GameSummary, StorageError> {
+    let id: String = row.get(0)?;
+
+    Ok(GameSummary {
+        id: RoomId::parse_str(&id).map_err(|err| StorageError {
+            message: err.to_string(),
+        })?,
+        users: [row.get(1)?, row.get(2)?],
+        winner: row.get(3)?,
+        reason: serde_json::from_str(&row.get::<_, String>(4)?)?,
+        config: serde_json::from_str(&row.get::<_, String>(5)?)?,
+        started_at: from_millis(row.get(6)?),
+        finished_at: from_millis(row.get(7)?),
+    })
+}
+
+/// Binds only the named parameters the statement actually uses.
+fn bind_named(statement: &mut Statement, params: &[(&str, &dyn ToSql)]) -> rusqlite::Result<()> {
+    for (name, value) in params {
+        if let Some(index) = statement.parameter_index(name)? {
+            statement.raw_bind_parameter(index, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn leaderboard_entry(row: &Row, rank: u64) -> rusqlite::Result<LeaderboardEntry> {
+    Ok(LeaderboardEntry {
+        rank,
+        user_id: row.get(0)?,
+        display_name: row.get(1)?,
+        rating: row.get(2)?,
+        wins: row.get(3)?,
+    })
+}
+
+impl MatchRepository for SqliteMatchRepository {
+    fn save_game(&self, game: &GameRecord, profiles: &[Profile]) -> Result<(), StorageError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let game_id = game.id.to_string();
+
+        transaction.execute(
+            "INSERT INTO games (id, first_user, second_user, winner, reason, config, started_at, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                game_id,
+                game.users[0],
+                game.users[1],
+                game.winner,
+                serde_json::to_string(&game.reason)?,
+                serde_json::to_string(&game.config)?,
+                to_millis(game.started_at),
+                to_millis(game.finished_at),
+            ],
+        )?;
+
+        for round in &game.rounds {
+            transaction.execute(
+                "INSERT INTO rounds (game_id, number, winner, started_at, finished_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    game_id,
+                    round.number,
+                    round.winner,
+                    to_millis(round.started_at),
+                    to_millis(round.finished_at),
+                ],
+            )?;
+
+            for user_move in &round.moves {
+                transaction.execute(
+                    "INSERT INTO moves (game_id, round_number, user_id, action, made_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        game_id,
+                        round.number,
+                        user_move.user_id,
+                        serde_json::to_string(&user_move.action)?,
+                        to_millis(user_move.made_at),
+                    ],
+                )?;
+            }
+        }
+
+        for profile in profiles {
+            transaction.execute(
+                "INSERT OR REPLACE INTO profiles (user_id, rating, games, wins, losses, draws)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    profile.user_id,
+                    profile.rating,
+                    profile.games,
+                    profile.wins,
+                    profile.losses,
+                    profile.draws,
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_profile(&self, user_id: UserId) -> Result<Option<Profile>, StorageError> {
+        let profile = self
+            .connection()
+            .query_row(
+                "SELECT user_id, rating, games, wins, losses, draws FROM profiles WHERE user_id = ?1",
+                params![user_id],
+                |row| {
+                    Ok(Profile {
+                        user_id: row.get(0)?,
+                        rating: row.get(1)?,
+                        games: row.get(2)?,
+                        wins: row.get(3)?,
+                        losses: row.get(4)?,
+                        draws: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(profile)
+    }
+
+    fn save_user(&self, user_id: UserId, display_name: &str) -> Result<(), StorageError> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO users (user_id, display_name) VALUES (?1, ?2)",
+            params![user_id, display_name],
+        )?;
+        Ok(())
+    }
+
+    fn leaderboard(&self, query: &LeaderboardQuery) -> Result<Leaderboard, StorageError> {
+        let connection = self.connection();
+
+        let (board, order, ahead) = match query.since {
+            None => (
+                ALL_TIME_BOARD,
+                "board.rating DESC, board.user_id",
+                "(-rating, user_id) < (-:rating, :user_id)",
+            ),
+            Some(_) => (
+                PERIOD_BOARD,
+                "board.wins DESC, board.rating DESC, board.user_id",
+                "(-wins, -rating, user_id) < (-:wins, -:rating, :user_id)",
+            ),
+        };
+        let filter = match query.scope {
+            LeaderboardScope::Global => "",
+            LeaderboardScope::Friends => FRIENDS_FILTER,
+        };
+        let board = format!(
+            "WITH board AS (SELECT * FROM ({board}) {filter})
+             SELECT board.user_id, users.display_name, board.rating, board.wins
+             FROM board LEFT JOIN users ON users.user_id = board.user_id"
+        );
+
+        let since = query.since.map(to_millis);
+        let params: &[(&str, &dyn ToSql)] = &[
+            (":user_id", &query.user_id),
+            (":since", &since),
+            (":limit", &query.limit),
+            (":offset", &query.offset),
+        ];
+
+        let mut statement = connection.prepare(&format!(
+            "{board} ORDER BY {order} LIMIT :limit OFFSET :offset"
+        ))?;
+        bind_named(&mut statement, params)?;
+        let mut rows = statement.raw_query();
+        let mut entries = vec![];
+        while let Some(row) = rows.next()? {
+            let rank = query.offset as u64 + entries.len() as u64 + 1;
+            entries.push(leaderboard_entry(row, rank)?);
+        }
+
+        let mut statement =
+            connection.prepare(&format!("{board} WHERE board.user_id = :user_id"))?;
+        bind_named(&mut statement, params)?;
+        let me = match statement.raw_query().next()? {
+            Some(row) => Some(leaderboard_entry(row, 0)?),
+            None => None,
+        };
+
+        let me = match me {
+            Some(mut me) => {
+                let mut statement = connection.prepare(&format!(
+                    "WITH board AS (SELECT * FROM ({board}) {filter})
+                     SELECT COUNT(*) FROM board WHERE {ahead}"
+                ))?;
+                bind_named(
+                    &mut statement,
+                    &[
+                        (":user_id", &query.user_id),
+                        (":since", &since),
+                        (":rating", &me.rating),
+                        (":wins", &me.wins),
+                    ],
+                )?;
+                let ahead: u64 = match statement.raw_query().next()? {
+                    Some(row) => row.get(0)?,
+                    None => 0,
+                };
+                me.rank = ahead + 1;
+                Some(me)
+            }
+            None => None,
+        };
+
+        Ok(Leaderboard { entries, me })
+    }
+
+    fn games(
+        &self,
+        user_id: UserId,
+        before: Option<&GamesCursor>,
+        limit: u32,
+    ) -> Result<Vec<GameSummary>, StorageError> {
+        let connection = self.connection();
+        let cursor_filter = match before {
+            Some(_) => "AND (finished_at, id) < (:finished_at, :id)",
+            None => "",
+        };
+
+        let mut statement = connection.prepare(&format!(
+            "SELECT {GAME_COLUMNS} FROM games
+             WHERE (first_user = :user_id OR second_user = :user_id) {cursor_filter}
+             ORDER BY finished_at DESC, id DESC
+             LIMIT :limit"
+        ))?;
+        let finished_at = before.map(|cursor| to_millis(cursor.finished_at));
+        let id = before.map(|cursor| cursor.id.to_string());
+        bind_named(
+            &mut statement,
+            &[
+                (":user_id", &user_id),
+                (":finished_at", &finished_at),
+                (":id", &id),
+                (":limit", &limit),
+            ],
+        )?;
+
+        let mut rows = statement.raw_query();
+        let mut games = vec![];
+        while let Some(row) = rows.next()? {
+            games.push(game_summary(row)?);
+        }
+
+        Ok(games)
+    }
+
+    fn game(&self, id: RoomId) -> Result<Option<GameRecord>, StorageError> {
+        let connection = self.connection();
+        let game_id = id.to_string();
+
+        let mut statement =
+            connection.prepare(&format!("SELECT {GAME_COLUMNS} FROM games WHERE id = ?1"))?;
+        let summary = match statement.query([&game_id])?.next()? {
+            Some(row) => game_summary(row)?,
+            None => return Ok(None),
+        };
+
+        let mut rounds = connection
+            .prepare(
+                "SELECT number, winner, started_at, finished_at FROM rounds
+                 WHERE game_id = ?1 ORDER BY number",
+            )?
+            .query_map([&game_id], |row| {
+                Ok(RoundRecord {
+                    number: row.get(0)?,
+                    winner: row.get(1)?,
+                    started_at: from_millis(row.get(2)?),
+                    finished_at: from_millis(row.get(3)?),
+                    moves: vec![],
+                })
+            })?
+            .collect::<Result<Vec<RoundRecord>, rusqlite::Error>>()?;
+
+        let mut statement = connection.prepare(
+            "SELECT round_number, user_id, action, made_at FROM moves
+             WHERE game_id = ?1 ORDER BY round_number, made_at",
+        )?;
+        let mut rows = statement.query([&game_id])?;
+        while let Some(row) = rows.next()? {
+            let round_number: u8 = row.get(0)?;
+            let user_move = MoveRecord {
+                user_id: row.get(1)?,
+                action: serde_json::from_str(&row.get::<_, String>(2)?)?,
+                made_at: from_millis(row.get(3)?),
+            };
+
+            if let Some(round) = rounds.iter_mut().find(|round| round.number == round_number) {
+                round.moves.push(user_move);
+            }
+        }
+
+        Ok(Some(GameRecord {
+            id: summary.id,
+            users: summary.users,
+            winner: summary.winner,
+            reason: summary.reason,
+            config: summary.config,
+            started_at: summary.started_at,
+            finished_at: summary.finished_at,
+            rounds,
+        }))
+    }
+
+    fn check(&self) -> Result<(), StorageError> {
+        self.connection().query_row("SELECT 1", [], |_| Ok(()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        rating::INITIAL_RATING,
+        room::{
+            actor::{Action, RoomConfig},
+            messages::FinishReason,
+        },
+    };
+
+    use super::*;
+
+    fn repository() -> SqliteMatchRepository {
+        let repository = SqliteMatchRepository::open(":memory:").unwrap();
+        repository.migrate().unwrap();
+        repository
+    }
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let repository = repository();
+        repository.migrate().unwrap();
+
+        let version: usize = repository
+            .connection()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        repository.check().unwrap();
+    }
+
+    #[test]
+    fn saved_game_is_read_back() {
+        let repository = repository();
+        let game = GameRecord {
+            id: RoomId::from_u128(1),
+            users: [1, 2],
+            winner: Some(2),
+            reason: FinishReason::Completed,
+            config: RoomConfig {
+                wins_required: 1,
+                max_rounds: Some(3),
+                ..RoomConfig::default()
+            },
+            started_at: at(1_000),
+            finished_at: at(9_000),
+            rounds: vec![
+                RoundRecord {
+                    number: 1,
+                    winner: None,
+                    started_at: at(1_000),
+                    finished_at: at(4_000),
+                    moves: vec![
+                        MoveRecord {
+                            user_id: 1,
+                            action: Action::Rock,
+                            made_at: at(2_000),
+                        },
+                        MoveRecord {
+                            user_id: 2,
+                            action: Action::Rock,
+                            made_at: at(3_000),
+                        },
+                    ],
+                },
+                RoundRecord {
+                    number: 2,
+                    winner: Some(2),
+                    started_at: at(4_000),
+                    finished_at: at(9_000),
+                    moves: vec![MoveRecord {
+                        user_id: 2,
+                        action: Action::Paper,
+                        made_at: at(5_000),
+                    }],
+                },
+            ],
+        };
+        let profile = Profile {
+            wins: 1,
+            games: 1,
+            rating: INITIAL_RATING + 16,
+            ..Profile::new(2)
+        };
+
+        repository
+            .save_game(&game, std::slice::from_ref(&profile))
+            .unwrap();
+
+        assert_eq!(repository.game(game.id).unwrap(), Some(game));
+        assert_eq!(repository.game(RoomId::from_u128(2)).unwrap(), None);
+        assert_eq!(repository.get_profile(2).unwrap(), Some(profile));
+        assert_eq!(repository.get_profile(3).unwrap(), None);
+    }
+}
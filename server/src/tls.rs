@@ -0,0 +1,3 @@
+pub mod certificates;
+pub mod error;
+pub mod redirect;
@@ -0,0 +1,149 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+
+use super::error::TlsError;
+
+/// Hands the latest loaded certificate to new handshakes, so a renewed certificate is picked up
+/// without restarting the server. Established connections keep the session they've negotiated.
+#[derive(Debug)]
+pub struct CertificateResolver {
+    cert_path: String,
+    key_path: String,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    /// Latest modification time of the files when they were read
+    modified: Option<SystemTime>,
+}
+
+impl CertificateResolver {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let modified = modified(cert_path, key_path);
+        let key = read(cert_path, key_path, &provider)?;
+
+        Ok(Self {
+            cert_path: cert_path.to_owned(),
+            key_path: key_path.to_owned(),
+            provider,
+            current: RwLock::new(Loaded {
+                key: Arc::new(key),
+                modified,
+            }),
+        })
+    }
+
+    /// Rustls config serving the certificates of the resolver.
+    pub fn server_config(self: &Arc<Self>) -> Result<ServerConfig, TlsError> {
+        let config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+
+        Ok(config)
+    }
+
+    /// Reads the files again if they've changed since they were loaded, returns whether they were.
+    /// The previous certificate is kept if the new one can't be loaded.
+    pub fn reload(&self) -> Result<bool, TlsError> {
+        let modified = modified(&self.cert_path, &self.key_path);
+        if modified.is_none() || modified == self.current().modified {
+            return Ok(false);
+        }
+
+        let key = read(&self.cert_path, &self.key_path, &self.provider)?;
+        *self.current.write().unwrap_or_else(|err| err.into_inner()) = Loaded {
+            key: Arc::new(key),
+            modified,
+        };
+
+        Ok(true)
+    }
+
+    /// Checks the files for a renewed certificate every `interval`.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut interval = actix_web::rt::time::interval(interval);
+        // The first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            match self.reload() {
+                Ok(true) => log::info!("Reloaded TLS certificate from {}", self.cert_path),
+                Ok(false) => (),
+                Err(err) => log::error!("Couldn't reload TLS certificate: {}", err),
+            }
+        }
+    }
+
+    fn current(&self) -> std::sync::RwLockReadGuard<'_, Loaded> {
+        self.current.read().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current().key.clone())
+    }
+}
+
+fn read(
+    cert_path: &str,
+    key_path: &str,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, TlsError> {
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| TlsError::Read {
+            path: cert_path.to_owned(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(cert_path.to_owned()));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|source| TlsError::Read {
+            path: key_path.to_owned(),
+            source,
+        })?
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.to_owned()))?;
+
+    Ok(CertifiedKey::from_der(certs, key, provider)?)
+}
+
+fn open(path: &str) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| TlsError::Read {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+/// Latest modification time of the files, renewal tools usually replace both of them.
+fn modified(cert_path: &str, key_path: &str) -> Option<SystemTime> {
+    let cert = fs::metadata(cert_path)
+        .and_then(|meta| meta.modified())
+        .ok()?;
+    let key = fs::metadata(key_path)
+        .and_then(|meta| meta.modified())
+        .ok()?;
+
+    Some(cert.max(key))
+}
@@ -0,0 +1,22 @@
+use derive_more::{Display, Error};
+
+#[derive(Debug, Display, Error)]
+pub enum TlsError {
+    #[display(fmt = "Couldn't read {}: {}", path, source)]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[display(fmt = "{} has no certificates", _0)]
+    NoCertificates(#[error(not(source))] String),
+    #[display(fmt = "{} has no private key", _0)]
+    NoPrivateKey(#[error(not(source))] String),
+    #[display(fmt = "Invalid certificate or key: {}", _0)]
+    Invalid(rustls::Error),
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(value: rustls::Error) -> Self {
+        Self::Invalid(value)
+    }
+}
@@ -0,0 +1,39 @@
+use actix_web::{
+    http::{header, uri::Authority},
+    web::Data,
+    HttpRequest, HttpResponse,
+};
+
+/// Default HTTPS port, it's left out of redirect locations.
+const DEFAULT_HTTPS_PORT: u16 = 443;
+
+/// Port HTTPS is served on.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpsPort(pub u16);
+
+/// Default service of the plain HTTP listener, sends every request to the same location over HTTPS.
+pub async fn redirect_to_https(req: HttpRequest, https_port: Data<HttpsPort>) -> HttpResponse {
+    let info = req.connection_info();
+    let Ok(authority) = info.host().parse::<Authority>() else {
+        return HttpResponse::BadRequest().finish();
+    };
+
+    let HttpsPort(https_port) = *https_port.get_ref();
+    let port = if https_port == DEFAULT_HTTPS_PORT {
+        String::new()
+    } else {
+        format!(":{}", https_port)
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((
+            header::LOCATION,
+            format!("https://{}{}{}", authority.host(), port, path),
+        ))
+        .finish()
+}
@@ -1,4 +1,33 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub type UserId = u64;
 pub type RoomId = Uuid;
+
+/// Machine-readable reason of an error sent to the client.
+/// Codes are part of the protocol: never rename or remove them, only add new ones.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The message couldn't be parsed
+    BadRequest,
+    InvalidConfig,
+    RoomNotFound,
+    NotInRoom,
+    ActionNotAllowed,
+    ActionAlreadySubmitted,
+    InviteCodeNotFound,
+    InviteCodeExpired,
+    RoomFull,
+    CannotJoinOwnRoom,
+    /// The other player is offline
+    OpponentGone,
+    RematchUnavailable,
+    /// The player is already in a running game
+    AlreadyInRoom,
+    /// New games can't be started until the server is restarted
+    ServerShuttingDown,
+    /// The client sends messages too fast, the message was dropped
+    RateLimited,
+    Internal,
+}
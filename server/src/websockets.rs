@@ -1,3 +1,7 @@
 pub mod client_messages;
 pub mod messages;
+pub mod protocol;
+pub mod rate_limit;
+#[cfg(test)]
+mod schema;
 pub mod ws;
@@ -1,33 +1,117 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::convert::From;
+use std::{
+    convert::From,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use uuid::Uuid;
 
 use crate::{
     room::{
-        actor::{Action, UserAction},
-        messages::MakeActionResult,
+        actor::{Action, RoomConfig, UserAction},
+        messages::{FinishReason, GameFinishedResult, MakeActionResult, RoomSnapshot},
+    },
+    server::{
+        error::ServerError,
+        messages::{MatchmakingStatus, ProcessClientMessageResult},
+    },
+    storage::{
+        messages::RatingChange,
+        repository::{LeaderboardEntry, LeaderboardPeriod, LeaderboardScope, Profile},
     },
-    server::messages::{MatchmakingStatus, ProcessClientMessageResult},
-    types::UserId,
+    types::{ErrorCode, UserId},
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(tag = "type", content = "data")]
 pub enum IncomingClientMessage {
-    StartMatchmaking,
+    StartMatchmaking(Option<RoomConfig>),
+    CancelMatchmaking,
+    CreatePrivateRoom(Option<RoomConfig>),
+    JoinPrivateRoom(JoinPrivateRoomPayload),
+    RequestRematch(RematchPayload),
+    DeclineRematch(RematchPayload),
     MakeAction(MakeActionPayload),
+    GetProfile(Option<GetProfilePayload>),
+    GetLeaderboard(Option<GetLeaderboardPayload>),
+}
+
+impl IncomingClientMessage {
+    /// Every value of `type`.
+    pub const TYPES: &'static [&'static str] = &[
+        "StartMatchmaking",
+        "CancelMatchmaking",
+        "CreatePrivateRoom",
+        "JoinPrivateRoom",
+        "RequestRematch",
+        "DeclineRematch",
+        "MakeAction",
+        "GetProfile",
+        "GetLeaderboard",
+    ];
+
+    /// Value of `type` the message is sent with.
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            IncomingClientMessage::StartMatchmaking(_) => "StartMatchmaking",
+            IncomingClientMessage::CancelMatchmaking => "CancelMatchmaking",
+            IncomingClientMessage::CreatePrivateRoom(_) => "CreatePrivateRoom",
+            IncomingClientMessage::JoinPrivateRoom(_) => "JoinPrivateRoom",
+            IncomingClientMessage::RequestRematch(_) => "RequestRematch",
+            IncomingClientMessage::DeclineRematch(_) => "DeclineRematch",
+            IncomingClientMessage::MakeAction(_) => "MakeAction",
+            IncomingClientMessage::GetProfile(_) => "GetProfile",
+            IncomingClientMessage::GetLeaderboard(_) => "GetLeaderboard",
+        }
+    }
+}
+
+/// Incoming message with an optional id chosen by the client.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct IncomingEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: IncomingClientMessage,
+}
+
+/// Reads only the id of a message that couldn't be parsed, so the error can still be matched.
+#[derive(Deserialize, Debug)]
+pub struct RequestId {
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Outgoing message. A direct reply carries the id of the request,
+/// messages the server pushes on its own don't have one.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct OutgoingEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: OutgoingClientMessage,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(tag = "type", content = "data")]
 pub enum OutgoingClientMessage {
     Error(ErrorPayload),
     ConfirmConnect(ConfirmConnectPayload),
     MatchmakingSuccess(MatchmakingSuccessPayload),
     MatchmakingStarted,
+    MatchmakingCancelled,
     MakeActionSuccess,
     RoundFinished(RoundFinishedPayload),
     GameFinished(GameFinishedPayload),
+    ResumeSession(RoomSnapshotPayload),
+    RoundStarted(RoundStartedPayload),
+    PrivateRoomCreated(PrivateRoomCreatedPayload),
+    RematchRequested,
+    RematchOffered(RematchPayload),
+    RematchDeclined(RematchPayload),
+    Profile(ProfilePayload),
+    Leaderboard(LeaderboardPayload),
+    ServerShuttingDown(ServerShuttingDownPayload),
 }
 
 impl From<ProcessClientMessageResult> for OutgoingClientMessage {
@@ -39,9 +123,23 @@ impl From<ProcessClientMessageResult> for OutgoingClientMessage {
                     OutgoingClientMessage::MatchmakingSuccess(MatchmakingSuccessPayload {
                         opponent: payload.opponent.unwrap(),
                         room: payload.room.unwrap(),
+                        config: payload.config,
                     })
                 }
             },
+            ProcessClientMessageResult::MatchmakingCancelled => {
+                OutgoingClientMessage::MatchmakingCancelled
+            }
+            ProcessClientMessageResult::PrivateRoomCreated(payload) => {
+                OutgoingClientMessage::PrivateRoomCreated(PrivateRoomCreatedPayload {
+                    code: payload.code,
+                    expires_at: to_unix_millis(payload.expires_at),
+                })
+            }
+            ProcessClientMessageResult::RematchRequested => OutgoingClientMessage::RematchRequested,
+            ProcessClientMessageResult::RematchDeclined(room) => {
+                OutgoingClientMessage::RematchDeclined(RematchPayload { room })
+            }
             ProcessClientMessageResult::MakeActionResult(payload) => match payload {
                 MakeActionResult::Accepted => OutgoingClientMessage::MakeActionSuccess,
                 MakeActionResult::RoundFinished(round_result) => {
@@ -56,43 +154,85 @@ impl From<ProcessClientMessageResult> for OutgoingClientMessage {
                     })
                 }
                 MakeActionResult::GameFinished(game_result) => {
-                    OutgoingClientMessage::GameFinished(GameFinishedPayload {
-                        winner: game_result.winner,
-                        actions: game_result
-                            .actions
-                            .iter()
-                            .map(|user_action| ActionHistory::from(*user_action))
-                            .collect(),
-                    })
+                    OutgoingClientMessage::GameFinished(GameFinishedPayload::from(game_result))
                 }
             },
+            ProcessClientMessageResult::Profile(profile) => {
+                OutgoingClientMessage::Profile(ProfilePayload::from(profile))
+            }
+            ProcessClientMessageResult::Leaderboard(leaderboard) => {
+                OutgoingClientMessage::Leaderboard(LeaderboardPayload {
+                    entries: leaderboard
+                        .entries
+                        .into_iter()
+                        .map(LeaderboardEntryPayload::from)
+                        .collect(),
+                    me: leaderboard.me.map(LeaderboardEntryPayload::from),
+                })
+            }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct ErrorPayload {
+    pub code: ErrorCode,
+    /// Human-readable description, may change at any time
     pub message: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl From<ServerError> for ErrorPayload {
+    fn from(value: ServerError) -> Self {
+        Self {
+            code: value.code,
+            message: value.message,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct ConfirmConnectPayload {
     pub message: String,
+    /// Version used for this connection
+    pub protocol_version: u16,
+    pub supported_versions: Vec<u16>,
+    /// Optional features the server has
+    pub capabilities: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MakeActionPayload {
     pub room: Uuid,
     pub action: Action,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct JoinPrivateRoomPayload {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct PrivateRoomCreatedPayload {
+    /// Invite code, can be shared as a `startapp` parameter of the mini app link
+    pub code: String,
+    /// Unix time in milliseconds
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RematchPayload {
+    /// The finished room
+    pub room: Uuid,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MatchmakingSuccessPayload {
     pub room: Uuid,
     pub opponent: UserId,
+    pub config: RoomConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct ActionHistory {
     pub user_id: UserId,
     pub action: Action,
@@ -107,15 +247,196 @@ impl From<UserAction> for ActionHistory {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct RoundFinishedPayload {
     pub winner: Option<UserId>,
     pub actions: Vec<ActionHistory>,
     pub next_round_count: u8,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct GameFinishedPayload {
     pub winner: Option<UserId>,
     pub actions: Vec<ActionHistory>,
+    pub reason: FinishReason,
+    /// Rating change of the receiving player, absent if the game is abandoned or couldn't be saved
+    pub rating: Option<RatingChangePayload>,
+}
+
+impl From<GameFinishedResult> for GameFinishedPayload {
+    fn from(value: GameFinishedResult) -> Self {
+        Self {
+            winner: value.winner,
+            actions: value
+                .actions
+                .iter()
+                .map(|user_action| ActionHistory::from(*user_action))
+                .collect(),
+            reason: value.reason,
+            rating: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RatingChangePayload {
+    /// The new rating
+    pub rating: i32,
+    pub delta: i32,
+}
+
+impl From<&RatingChange> for RatingChangePayload {
+    fn from(value: &RatingChange) -> Self {
+        Self {
+            rating: value.rating,
+            delta: value.delta,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct GetProfilePayload {
+    /// Defaults to the requesting user
+    pub user_id: Option<UserId>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ProfilePayload {
+    pub user_id: UserId,
+    pub rating: i32,
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl From<Profile> for ProfilePayload {
+    fn from(value: Profile) -> Self {
+        Self {
+            user_id: value.user_id,
+            rating: value.rating,
+            games: value.games,
+            wins: value.wins,
+            losses: value.losses,
+            draws: value.draws,
+        }
+    }
+}
+
+/// The biggest page of a leaderboard.
+pub const MAX_LEADERBOARD_LIMIT: u32 = 100;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(default)]
+pub struct GetLeaderboardPayload {
+    pub scope: LeaderboardScope,
+    pub period: LeaderboardPeriod,
+    pub offset: u32,
+    /// Capped by `MAX_LEADERBOARD_LIMIT`
+    pub limit: u32,
+}
+
+impl Default for GetLeaderboardPayload {
+    fn default() -> Self {
+        Self {
+            scope: LeaderboardScope::default(),
+            period: LeaderboardPeriod::default(),
+            offset: 0,
+            limit: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct LeaderboardEntryPayload {
+    pub rank: u64,
+    pub user_id: UserId,
+    pub display_name: Option<String>,
+    pub rating: i32,
+    pub wins: u32,
+}
+
+impl From<LeaderboardEntry> for LeaderboardEntryPayload {
+    fn from(value: LeaderboardEntry) -> Self {
+        Self {
+            rank: value.rank,
+            user_id: value.user_id,
+            display_name: value.display_name,
+            rating: value.rating,
+            wins: value.wins,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct LeaderboardPayload {
+    pub entries: Vec<LeaderboardEntryPayload>,
+    /// The requesting user, `None` until they are ranked
+    pub me: Option<LeaderboardEntryPayload>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RoundHistory {
+    pub winner: Option<UserId>,
+    pub actions: Vec<ActionHistory>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RoomSnapshotPayload {
+    pub room: Uuid,
+    pub opponent: UserId,
+    pub rounds: Vec<RoundHistory>,
+    pub wins: u8,
+    pub opponent_wins: u8,
+    pub next_round_count: u8,
+    pub my_action: Option<Action>,
+    pub deadline: u64,
+    pub config: RoomConfig,
+}
+
+impl From<RoomSnapshot> for RoomSnapshotPayload {
+    fn from(value: RoomSnapshot) -> Self {
+        Self {
+            room: value.room_id,
+            opponent: value.opponent,
+            rounds: value
+                .rounds
+                .into_iter()
+                .map(|round| RoundHistory {
+                    winner: round.winner,
+                    actions: round
+                        .actions
+                        .iter()
+                        .map(|user_action| ActionHistory::from(*user_action))
+                        .collect(),
+                })
+                .collect(),
+            wins: value.wins,
+            opponent_wins: value.opponent_wins,
+            next_round_count: value.next_round_count,
+            my_action: value.my_action,
+            deadline: to_unix_millis(value.deadline),
+            config: value.config,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ServerShuttingDownPayload {
+    /// Running games can be finished within this time, then the connection is closed
+    pub grace_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RoundStartedPayload {
+    pub room: Uuid,
+    pub round: u8,
+    /// Unix time in milliseconds when the round is resolved without missing actions
+    pub deadline: u64,
+}
+
+pub fn to_unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
 }
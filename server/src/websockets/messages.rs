@@ -1,10 +1,12 @@
 use actix::Message;
+use actix_web_actors::ws::CloseCode;
 
 use super::client_messages::OutgoingClientMessage;
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Close {
+    pub code: CloseCode,
     pub reason: String,
 }
 
@@ -0,0 +1,86 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::client_messages::OutgoingClientMessage;
+
+/// Version of the protocol in `client_messages.rs`.
+/// Bump it on every change an already deployed client can't handle.
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// The oldest version still served, so cached Mini App bundles keep working during rollouts.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Version of clients which don't declare one. Bundles built before versioning are version 1.
+pub const DEFAULT_PROTOCOL_VERSION: u16 = 1;
+
+/// Close code sent to clients with a version the server doesn't support.
+pub const UNSUPPORTED_PROTOCOL_CLOSE_CODE: u16 = 4001;
+
+/// Optional features of the current protocol version.
+pub const CAPABILITIES: &[&str] = &[
+    "request_id",
+    "error_codes",
+    "session_resume",
+    "round_timer",
+    "private_rooms",
+    "rematch",
+    "rulesets",
+    "ratings",
+    "leaderboard",
+    "msgpack",
+    "shutdown_notice",
+    "rate_limits",
+];
+
+/// Encoding of messages chosen by the client when connecting.
+/// Incoming messages are accepted in both encodings: JSON in text frames, MessagePack in binary frames.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+/// Encodes structs as maps and ids as strings, so the messages look the same as in JSON.
+pub fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut bytes = vec![];
+    value.serialize(
+        &mut rmp_serde::Serializer::new(&mut bytes)
+            .with_struct_map()
+            .with_human_readable(),
+    )?;
+
+    Ok(bytes)
+}
+
+pub fn from_msgpack<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, rmp_serde::decode::Error> {
+    T::deserialize(&mut rmp_serde::Deserializer::new(bytes).with_human_readable())
+}
+
+pub fn is_supported(version: u16) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+pub fn supported_versions() -> Vec<u16> {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).collect()
+}
+
+/// Adapts an outgoing message to the version of the client, `None` if the client can't handle it.
+pub fn downgrade(version: u16, message: OutgoingClientMessage) -> Option<OutgoingClientMessage> {
+    if version >= PROTOCOL_VERSION {
+        return Some(message);
+    }
+
+    // Version 1 clients know only the messages of the original protocol
+    match message {
+        OutgoingClientMessage::Error(_)
+        | OutgoingClientMessage::ConfirmConnect(_)
+        | OutgoingClientMessage::MatchmakingSuccess(_)
+        | OutgoingClientMessage::MatchmakingStarted
+        | OutgoingClientMessage::MakeActionSuccess
+        | OutgoingClientMessage::RoundFinished(_)
+        | OutgoingClientMessage::GameFinished(_) => Some(message),
+        _ => None,
+    }
+}
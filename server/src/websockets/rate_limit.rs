@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::settings::{BucketConfig, WebsocketConfig},
+    websockets::client_messages::IncomingClientMessage,
+};
+
+/// What to do with a message of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Limited, ignored without an answer
+    Drop,
+    /// Limited, answered with the `RateLimited` error
+    Reject,
+    /// Limited too many times in a row, the connection is closed
+    Close,
+}
+
+struct TokenBucket {
+    config: BucketConfig,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: BucketConfig, now: Instant) -> Self {
+        Self {
+            config,
+            tokens: config.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.config.per_second).min(self.config.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+}
+
+/// Token buckets of a connection, one for every frame and one per limited message type.
+pub struct RateLimiter {
+    messages: TokenBucket,
+    message_types: HashMap<&'static str, TokenBucket>,
+    silent_violations: u32,
+    max_violations: u32,
+    violation_window: Duration,
+    /// Limited messages in a row
+    violations: u32,
+    last_violation: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &WebsocketConfig) -> Self {
+        let now = Instant::now();
+
+        Self {
+            messages: TokenBucket::new(config.rate_limit, now),
+            message_types: IncomingClientMessage::TYPES
+                .iter()
+                .filter_map(|message_type| {
+                    let bucket = config.message_rate_limits.get(*message_type)?;
+                    Some((*message_type, TokenBucket::new(*bucket, now)))
+                })
+                .collect(),
+            silent_violations: config.silent_violations,
+            max_violations: config.max_violations,
+            violation_window: config.violation_window,
+            violations: 0,
+            last_violation: None,
+        }
+    }
+
+    /// Takes a token for a frame with a message of the given type, `None` if it couldn't be parsed.
+    /// Nothing is taken if the message is limited.
+    pub fn check(&mut self, message_type: Option<&str>, now: Instant) -> Verdict {
+        self.messages.refill(now);
+        let mut bucket =
+            message_type.and_then(|message_type| self.message_types.get_mut(message_type));
+        if let Some(bucket) = bucket.as_mut() {
+            bucket.refill(now);
+        }
+
+        let is_allowed =
+            self.messages.has_token() && bucket.as_ref().is_none_or(|bucket| bucket.has_token());
+        if is_allowed {
+            self.messages.tokens -= 1.0;
+            if let Some(bucket) = bucket {
+                bucket.tokens -= 1.0;
+            }
+            return Verdict::Allow;
+        }
+
+        let is_in_a_row = self
+            .last_violation
+            .is_some_and(|last| now.saturating_duration_since(last) < self.violation_window);
+        self.violations = if is_in_a_row { self.violations + 1 } else { 1 };
+        self.last_violation = Some(now);
+
+        if self.violations >= self.max_violations {
+            Verdict::Close
+        } else if self.violations > self.silent_violations {
+            Verdict::Reject
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn limiter(burst: u32, message_rate_limits: &[(&str, u32)]) -> RateLimiter {
+        RateLimiter::new(&WebsocketConfig {
+            rate_limit: BucketConfig::new(burst, 1.0),
+            message_rate_limits: message_rate_limits
+                .iter()
+                .map(|(message_type, burst)| {
+                    (message_type.to_string(), BucketConfig::new(*burst, 1.0))
+                })
+                .collect(),
+            silent_violations: 2,
+            max_violations: 4,
+            violation_window: WINDOW,
+            ..WebsocketConfig::default()
+        })
+    }
+
+    fn verdicts(
+        limiter: &mut RateLimiter,
+        message_type: Option<&str>,
+        count: usize,
+        now: Instant,
+    ) -> Vec<Verdict> {
+        (0..count)
+            .map(|_| limiter.check(message_type, now))
+            .collect()
+    }
+
+    #[test]
+    fn connection_bucket_limits_every_frame() {
+        let mut limiter = limiter(3, &[]);
+        let now = Instant::now();
+
+        assert_eq!(
+            verdicts(&mut limiter, None, 2, now),
+            [Verdict::Allow, Verdict::Allow]
+        );
+        assert_eq!(
+            verdicts(&mut limiter, Some("GetProfile"), 2, now),
+            [Verdict::Allow, Verdict::Drop]
+        );
+    }
+
+    #[test]
+    fn message_type_bucket_limits_only_its_type() {
+        let mut limiter = limiter(4, &[("MakeAction", 2)]);
+        let now = Instant::now();
+
+        assert_eq!(
+            verdicts(&mut limiter, Some("MakeAction"), 3, now),
+            [Verdict::Allow, Verdict::Allow, Verdict::Drop]
+        );
+        // The limited message hasn't taken a token of the connection
+        assert_eq!(
+            verdicts(&mut limiter, Some("GetProfile"), 3, now),
+            [Verdict::Allow, Verdict::Allow, Verdict::Drop]
+        );
+    }
+
+    #[test]
+    fn violations_escalate_to_close() {
+        let mut limiter = limiter(1, &[]);
+        let now = Instant::now();
+
+        assert_eq!(
+            verdicts(&mut limiter, None, 5, now),
+            [
+                Verdict::Allow,
+                Verdict::Drop,
+                Verdict::Drop,
+                Verdict::Reject,
+                Verdict::Close
+            ]
+        );
+    }
+
+    #[test]
+    fn buckets_are_refilled_over_time() {
+        let mut limiter = limiter(2, &[("MakeAction", 1)]);
+        let now = Instant::now();
+
+        assert_eq!(
+            verdicts(&mut limiter, Some("MakeAction"), 2, now),
+            [Verdict::Allow, Verdict::Drop]
+        );
+        assert_eq!(
+            limiter.check(Some("MakeAction"), now + Duration::from_millis(500)),
+            Verdict::Drop
+        );
+        assert_eq!(
+            limiter.check(Some("MakeAction"), now + Duration::from_secs(1)),
+            Verdict::Allow
+        );
+        // Refilled up to the burst only
+        assert_eq!(
+            verdicts(&mut limiter, None, 3, now + Duration::from_secs(60)),
+            [Verdict::Allow, Verdict::Allow, Verdict::Drop]
+        );
+    }
+
+    #[test]
+    fn violations_are_counted_again_after_the_window() {
+        let mut limiter = limiter(1, &[("MakeAction", 1)]);
+        let now = Instant::now();
+
+        assert_eq!(
+            verdicts(&mut limiter, Some("MakeAction"), 4, now),
+            [
+                Verdict::Allow,
+                Verdict::Drop,
+                Verdict::Drop,
+                Verdict::Reject
+            ]
+        );
+        // Nothing is limited for the whole window, so limited messages are dropped silently again
+        let later = now + WINDOW;
+        assert_eq!(limiter.check(Some("MakeAction"), later), Verdict::Allow);
+        assert_eq!(
+            verdicts(&mut limiter, Some("MakeAction"), 3, later),
+            [Verdict::Drop, Verdict::Drop, Verdict::Reject]
+        );
+    }
+}
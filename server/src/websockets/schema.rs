@@ -0,0 +1,226 @@
+use std::{env, fs, io, path::PathBuf};
+
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Map, Value};
+
+use super::client_messages::{IncomingEnvelope, OutgoingEnvelope};
+
+/// Command that regenerates the files.
+const EXPORT_COMMAND: &str = "UPDATE_PROTOCOL=1 cargo test protocol_files";
+
+/// Set to regenerate the files instead of checking them.
+const UPDATE_ENV: &str = "UPDATE_PROTOCOL";
+
+/// JSON Schema of every message, kept next to the server sources.
+fn schema_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("protocol.schema.json")
+}
+
+/// TypeScript definitions used by the client.
+fn typescript_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client/src/lib/types/protocol.ts")
+}
+
+/// The generated files are checked in, so the client and CI don't need to build the server to get them.
+#[test]
+fn protocol_files_are_up_to_date() {
+    let schema = schema();
+    let expected = [
+        (schema_path(), render_schema(&schema).unwrap()),
+        (typescript_path(), render_typescript(&schema)),
+    ];
+
+    for (path, content) in expected {
+        if env::var_os(UPDATE_ENV).is_some() {
+            fs::write(&path, content).unwrap();
+            continue;
+        }
+
+        let actual = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            actual == content,
+            "{} is stale, run `{}`",
+            path.display(),
+            EXPORT_COMMAND
+        );
+    }
+}
+
+fn schema() -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let incoming = generator.subschema_for::<IncomingEnvelope>();
+    let outgoing = generator.subschema_for::<OutgoingEnvelope>();
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Protocol",
+        "description": "Messages of the websocket protocol",
+        "anyOf": [incoming, outgoing],
+        "definitions": generator.definitions(),
+    })
+}
+
+fn render_schema(schema: &Value) -> io::Result<String> {
+    let mut content = serde_json::to_string_pretty(schema)?;
+    content.push('\n');
+
+    Ok(content)
+}
+
+fn render_typescript(schema: &Value) -> String {
+    let mut content = format!(
+        "// Generated from the server protocol types by `{}`, do not edit.\n",
+        EXPORT_COMMAND
+    );
+
+    let definitions = schema["definitions"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    for (name, definition) in &definitions {
+        content.push('\n');
+        content.push_str(&comment(definition, ""));
+        match string_enum(definition) {
+            Some(variants) => {
+                content.push_str(&format!("export enum {} {{\n", name));
+                for (variant, description) in variants {
+                    if let Some(description) = description {
+                        content.push_str(&comment(&json!({ "description": description }), "    "));
+                    }
+                    content.push_str(&format!("    {} = '{}',\n", variant, variant));
+                }
+                content.push_str("}\n");
+            }
+            None => {
+                content.push_str(&format!(
+                    "export type {} = {};\n",
+                    name,
+                    typescript_type(definition, "")
+                ));
+            }
+        }
+    }
+
+    content
+}
+
+/// Variants and their descriptions if the schema is an enum of unit variants.
+fn string_enum(schema: &Value) -> Option<Vec<(String, Option<String>)>> {
+    if let Some(values) = schema["enum"].as_array() {
+        return values
+            .iter()
+            .map(|value| Some((value.as_str()?.to_owned(), None)))
+            .collect();
+    }
+
+    // Documented variants get a schema of their own
+    let mut variants = vec![];
+    for variant in schema["oneOf"].as_array()? {
+        let description = variant["description"].as_str().map(str::to_owned);
+        for value in variant["enum"].as_array()? {
+            variants.push((value.as_str()?.to_owned(), description.clone()));
+        }
+    }
+
+    Some(variants)
+}
+
+fn comment(schema: &Value, indent: &str) -> String {
+    schema["description"]
+        .as_str()
+        .map(|description| {
+            description
+                .lines()
+                .map(|line| format!("{}// {}\n", indent, line))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn typescript_type(schema: &Value, indent: &str) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference.rsplit('/').next().unwrap_or(reference).to_owned();
+    }
+
+    if let Some(values) = schema["enum"].as_array() {
+        return values
+            .iter()
+            .map(|value| value.to_string().replace('"', "'"))
+            .collect::<Vec<String>>()
+            .join(" | ");
+    }
+
+    for key in ["allOf", "anyOf", "oneOf"] {
+        let Some(schemas) = schema[key].as_array() else {
+            continue;
+        };
+        let types = union(schemas, indent);
+        // Flattened enums are merged into the object that contains them
+        if schema["properties"].is_object() {
+            return format!("{} & ({})", object(schema, indent), types);
+        }
+
+        return types;
+    }
+
+    match &schema["type"] {
+        Value::Array(types) => types
+            .iter()
+            .map(|ty| {
+                let mut schema = schema.clone();
+                schema["type"] = ty.clone();
+                typescript_type(&schema, indent)
+            })
+            .collect::<Vec<String>>()
+            .join(" | "),
+        Value::String(ty) => match ty.as_str() {
+            "null" => "null".to_owned(),
+            "boolean" => "boolean".to_owned(),
+            "integer" | "number" => "number".to_owned(),
+            "string" => "string".to_owned(),
+            "array" => {
+                let item = typescript_type(&schema["items"], indent);
+                if item.contains(' ') {
+                    format!("({})[]", item)
+                } else {
+                    format!("{}[]", item)
+                }
+            }
+            "object" => object(schema, indent),
+            _ => "unknown".to_owned(),
+        },
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn union(schemas: &[Value], indent: &str) -> String {
+    schemas
+        .iter()
+        .map(|schema| typescript_type(schema, indent))
+        .collect::<Vec<String>>()
+        .join(" | ")
+}
+
+fn object(schema: &Value, indent: &str) -> String {
+    let empty = Map::new();
+    let properties = schema["properties"].as_object().unwrap_or(&empty);
+    let required = schema["required"].as_array();
+    let inner = format!("{}    ", indent);
+
+    let mut content = "{\n".to_owned();
+    for (name, property) in properties {
+        let optional = !required.is_some_and(|required| required.contains(&json!(name)));
+        content.push_str(&comment(property, &inner));
+        content.push_str(&format!(
+            "{}{}{}: {},\n",
+            inner,
+            name,
+            if optional { "?" } else { "" },
+            typescript_type(property, &inner)
+        ));
+    }
+    content.push_str(indent);
+    content.push('}');
+
+    content
+}
@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use actix::{
     fut, Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, ContextFutureSpawner, Handler,
@@ -7,47 +7,228 @@ use actix::{
 use actix_web_actors::ws::{self, CloseCode, CloseReason, WebsocketContext};
 
 use super::{
-    client_messages::{ConfirmConnectPayload, IncomingClientMessage, OutgoingClientMessage},
+    client_messages::{
+        ConfirmConnectPayload, IncomingEnvelope, OutgoingClientMessage, OutgoingEnvelope, RequestId,
+    },
     messages::{Close, SendClientMessage},
+    protocol::{self, Encoding},
+    rate_limit::{RateLimiter, Verdict},
 };
 use crate::{
+    config::settings::WebsocketConfig,
+    metrics::registry::Metrics,
     server::{
         actor::Server,
-        messages::{AttachConnection, ProcessClientMessage},
+        error::ServerError,
+        messages::{AttachConnection, DetachConnection, ProcessClientMessage},
     },
-    types::UserId,
+    types::{ErrorCode, UserId},
     websockets::client_messages::ErrorPayload,
 };
 
-const PING_INTERVAL: Duration = Duration::from_secs(5);
-
-const TIMEOUT: Duration = Duration::from_secs(10);
-
 pub struct Connection {
     user_id: UserId,
+    /// Name from Telegram shown to other players
+    display_name: String,
+    /// Version of the protocol the client has declared
+    protocol_version: u16,
+    /// Encoding of outgoing messages chosen by the client
+    encoding: Encoding,
     server: Addr<Server>,
+    metrics: Metrics,
+    settings: WebsocketConfig,
+    rate_limiter: RateLimiter,
     last_ping: Instant,
 }
 
 impl Connection {
-    pub fn new(user_id: UserId, server: Addr<Server>) -> Self {
+    pub fn new(
+        user_id: UserId,
+        display_name: String,
+        protocol_version: u16,
+        encoding: Encoding,
+        server: Addr<Server>,
+        metrics: Metrics,
+        settings: WebsocketConfig,
+    ) -> Self {
         Self {
             user_id,
+            display_name,
+            protocol_version,
+            encoding,
             server,
+            metrics,
+            rate_limiter: RateLimiter::new(&settings),
+            settings,
             last_ping: Instant::now(),
         }
     }
 
     fn send_message(&self, msg: OutgoingClientMessage, ctx: &mut ws::WebsocketContext<Self>) {
-        match serde_json::to_string(&msg) {
-            Ok(txt) => ctx.text(txt),
-            Err(err) => log::error!("Couldn't serialize a message: {}", err),
+        self.send_envelope(
+            OutgoingEnvelope {
+                request_id: None,
+                message: msg,
+            },
+            ctx,
+        );
+    }
+
+    /// Answers the request with the given id.
+    fn send_reply(
+        &self,
+        request_id: Option<String>,
+        msg: OutgoingClientMessage,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.send_envelope(
+            OutgoingEnvelope {
+                request_id,
+                message: msg,
+            },
+            ctx,
+        );
+    }
+
+    /// Passes the message to the server and replies with the result.
+    fn process_envelope(
+        &mut self,
+        envelope: IncomingEnvelope,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let IncomingEnvelope {
+            request_id,
+            message,
+        } = envelope;
+        log::debug!("Event from user {} with type {:#?}", self.user_id, message);
+        if !self.check_rate_limit(Some(message.message_type()), &request_id, ctx) {
+            return;
+        }
+
+        let sent_at = Instant::now();
+        self.server
+            .send(ProcessClientMessage {
+                message,
+                user_id: self.user_id,
+            })
+            .into_actor(self)
+            .then(move |res, conn, ctx| {
+                conn.metrics
+                    .message_latency
+                    .observe(sent_at.elapsed().as_secs_f64());
+                let res = res.unwrap_or_else(|err| {
+                    log::error!("Couldn't send message to actor: {}", err);
+                    Err(ServerError::internal())
+                });
+
+                match res {
+                    Ok(result) => {
+                        conn.send_reply(request_id, OutgoingClientMessage::from(result), ctx);
+                    }
+                    Err(err) => {
+                        log::debug!("Process message error: {:?}", err);
+                        conn.send_reply(
+                            request_id,
+                            OutgoingClientMessage::Error(ErrorPayload::from(err)),
+                            ctx,
+                        );
+                    }
+                }
+
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// Takes a token for the frame, returns whether its message can be processed.
+    /// A client over the limits is ignored at first, then answered with an error, then disconnected.
+    fn check_rate_limit(
+        &mut self,
+        message_type: Option<&'static str>,
+        request_id: &Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> bool {
+        let verdict = self.rate_limiter.check(message_type, Instant::now());
+        if verdict == Verdict::Allow {
+            return true;
+        }
+
+        self.metrics
+            .rate_limited_messages
+            .with_label_values(&[message_type.unwrap_or("unparsed")])
+            .inc();
+        match verdict {
+            Verdict::Allow | Verdict::Drop => (),
+            Verdict::Reject => {
+                log::info!("User {} is rate limited", self.user_id);
+                self.send_reply(
+                    request_id.clone(),
+                    OutgoingClientMessage::Error(ErrorPayload {
+                        code: ErrorCode::RateLimited,
+                        message: "Too many messages".to_owned(),
+                    }),
+                    ctx,
+                );
+            }
+            Verdict::Close => {
+                log::warn!("User {} is disconnected for flooding", self.user_id);
+                self.close_for_policy(CloseCode::Policy, "Too many messages", ctx);
+            }
+        }
+
+        false
+    }
+
+    fn close_for_policy(
+        &self,
+        code: CloseCode,
+        reason: &str,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.metrics.policy_disconnects.inc();
+        ctx.close(Some(CloseReason {
+            code,
+            description: Some(reason.to_owned()),
+        }));
+        ctx.stop();
+    }
+
+    fn reply_bad_request(&self, request_id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) {
+        self.metrics.protocol_errors.inc();
+        self.send_reply(
+            request_id,
+            OutgoingClientMessage::Error(ErrorPayload {
+                code: ErrorCode::BadRequest,
+                message: "Bad request".to_owned(),
+            }),
+            ctx,
+        );
+    }
+
+    fn send_envelope(&self, envelope: OutgoingEnvelope, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(message) = protocol::downgrade(self.protocol_version, envelope.message) else {
+            return;
+        };
+        let envelope = OutgoingEnvelope {
+            request_id: envelope.request_id,
+            message,
+        };
+
+        match self.encoding {
+            Encoding::Json => match serde_json::to_string(&envelope) {
+                Ok(txt) => ctx.text(txt),
+                Err(err) => log::error!("Couldn't serialize a message: {}", err),
+            },
+            Encoding::MessagePack => match protocol::to_msgpack(&envelope) {
+                Ok(bytes) => ctx.binary(bytes),
+                Err(err) => log::error!("Couldn't serialize a message: {}", err),
+            },
         }
     }
 
     fn ping(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(PING_INTERVAL, |actor, context| {
-            if Instant::now().duration_since(actor.last_ping) > TIMEOUT {
+        ctx.run_interval(self.settings.ping_interval, |actor, context| {
+            if Instant::now().duration_since(actor.last_ping) > actor.settings.timeout {
                 log::info!("Connection timout for user {}", actor.user_id);
                 context.stop();
                 return;
@@ -61,25 +242,60 @@ impl Actor for Connection {
     type Context = WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        self.metrics.connections.inc();
+        if !protocol::is_supported(self.protocol_version) {
+            log::info!(
+                "User {} has unsupported protocol version {}",
+                self.user_id,
+                self.protocol_version
+            );
+            ctx.close(Some(CloseReason {
+                code: CloseCode::Other(protocol::UNSUPPORTED_PROTOCOL_CLOSE_CODE),
+                description: Some(format!(
+                    "Unsupported protocol version, supported versions are {}-{}",
+                    protocol::MIN_PROTOCOL_VERSION,
+                    protocol::PROTOCOL_VERSION
+                )),
+            }));
+            ctx.stop();
+            return;
+        }
+
         self.ping(ctx);
         let addr = ctx.address();
 
         self.server.do_send(AttachConnection {
             connection: addr,
             user_id: self.user_id,
+            display_name: self.display_name.clone(),
         });
 
         self.send_message(
             OutgoingClientMessage::ConfirmConnect(ConfirmConnectPayload {
                 message: "Connection established".to_owned(),
+                protocol_version: self.protocol_version,
+                supported_versions: protocol::supported_versions(),
+                capabilities: protocol::CAPABILITIES
+                    .iter()
+                    .map(|capability| capability.to_string())
+                    .collect(),
             }),
             ctx,
         )
     }
 
-    fn stopping(&mut self, _ctx: &mut Self::Context) -> actix::Running {
+    fn stopping(&mut self, ctx: &mut Self::Context) -> actix::Running {
+        self.server.do_send(DetachConnection {
+            connection: ctx.address(),
+            user_id: self.user_id,
+        });
+
         actix::Running::Stop
     }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.metrics.connections.dec();
+    }
 }
 
 impl Handler<Close> for Connection {
@@ -87,7 +303,7 @@ impl Handler<Close> for Connection {
 
     fn handle(&mut self, msg: Close, ctx: &mut Self::Context) -> Self::Result {
         ctx.close(Some(CloseReason {
-            code: CloseCode::Normal,
+            code: msg.code,
             description: Some(msg.reason),
         }));
     }
@@ -103,60 +319,51 @@ impl Handler<SendClientMessage> for Connection {
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Connection {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        if let Err(ws::ProtocolError::Overflow) = msg {
+            log::warn!("User {} has sent a too large frame", self.user_id);
+            self.close_for_policy(CloseCode::Size, "Frame is too large", ctx);
+            return;
+        }
         if msg.is_err() {
             log::error!("There is an error with ws message: {}", msg.err().unwrap());
+            self.metrics.protocol_errors.inc();
             return;
         }
 
         match msg.unwrap() {
-            ws::Message::Text(msg) => match serde_json::from_str::<IncomingClientMessage>(&msg) {
-                Ok(message) => {
-                    log::debug!("Event from user {} with type {:#?}", self.user_id, message);
-                    self.server
-                        .send(ProcessClientMessage {
-                            message,
-                            user_id: self.user_id,
-                        })
-                        .into_actor(self)
-                        .then(|res, conn, ctx| {
-                            if let Err(err) = res {
-                                log::error!("Couldn't send message to actor: {}", err);
-                                return fut::ready(());
-                            }
-
-                            match res.unwrap() {
-                                Ok(result) => {
-                                    conn.send_message(OutgoingClientMessage::from(result), ctx);
-                                }
-                                Err(err) => {
-                                    log::error!("Process message error: {}", err);
-                                    conn.send_message(
-                                        OutgoingClientMessage::Error(ErrorPayload {
-                                            message: "Internal error: Couldn't process event"
-                                                .to_owned(),
-                                        }),
-                                        ctx,
-                                    );
-                                }
-                            }
-
-                            fut::ready(())
-                        })
-                        .wait(ctx);
+            ws::Message::Text(msg) => match serde_json::from_str::<IncomingEnvelope>(&msg) {
+                Ok(envelope) => self.process_envelope(envelope, ctx),
+                Err(err) => {
+                    let request_id = serde_json::from_str::<RequestId>(&msg)
+                        .ok()
+                        .and_then(|id| id.request_id);
+                    if self.check_rate_limit(None, &request_id, ctx) {
+                        log::warn!(
+                            "Couldn't parse message from user {} because of {}",
+                            self.user_id,
+                            err
+                        );
+                        self.reply_bad_request(request_id, ctx);
+                    }
                 }
+            },
+            ws::Message::Binary(msg) => match protocol::from_msgpack::<IncomingEnvelope>(&msg) {
+                Ok(envelope) => self.process_envelope(envelope, ctx),
                 Err(err) => {
-                    log::warn!("Couldn't parse message: {} because of {}", msg, err);
-                    self.send_message(
-                        OutgoingClientMessage::Error(ErrorPayload {
-                            message: "Bad request".to_owned(),
-                        }),
-                        ctx,
-                    )
+                    let request_id = protocol::from_msgpack::<RequestId>(&msg)
+                        .ok()
+                        .and_then(|id| id.request_id);
+                    if self.check_rate_limit(None, &request_id, ctx) {
+                        log::warn!(
+                            "Couldn't parse binary message from user {} because of {}",
+                            self.user_id,
+                            err
+                        );
+                        self.reply_bad_request(request_id, ctx);
+                    }
                 }
             },
 
-            // Ignore for now
-            ws::Message::Binary(_) => (),
             // Ignore for now
             ws::Message::Continuation(_) => (),
 
@@ -169,8 +376,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Connection {
                 log::debug!("Pong from user {}", self.user_id);
                 self.last_ping = Instant::now();
             }
-            // TODO notify the server
-            ws::Message::Close(msg) => ctx.close(msg),
+            ws::Message::Close(msg) => {
+                ctx.close(msg);
+                ctx.stop();
+            }
             ws::Message::Nop => (),
         }
     }